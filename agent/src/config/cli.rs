@@ -0,0 +1,297 @@
+use clap::{Args, Parser, Subcommand};
+use std::collections::HashMap;
+
+/// Command line of the agent. Without a subcommand it runs the pass the
+/// environment selects, as it did before subcommands existed.
+#[derive(Parser)]
+#[command(about = "Verifies, backs up and restores the files under a storage directory")]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Flags taking precedence over the environment variable of the same name.
+#[derive(Args)]
+pub struct GlobalArgs {
+    /// File of NAME=value lines read before the environment, again on reload (ENV_FILE)
+    #[arg(long, global = true)]
+    env_file: Option<String>,
+    /// Directory holding the files to protect (STORAGE_DIRECTORY)
+    #[arg(long, global = true)]
+    storage_directory: Option<String>,
+    /// Encrypted backup directory (VAULT_DIRECTORY)
+    #[arg(long, global = true)]
+    vault_directory: Option<String>,
+    /// Reed-Solomon parity directory (PARITY_DIRECTORY)
+    #[arg(long, global = true)]
+    parity_directory: Option<String>,
+    /// Mirror of the storage directory used to repair chunks (REPLICA_DIRECTORY)
+    #[arg(long, global = true)]
+    replica_directory: Option<String>,
+    /// Signature store backend: mongodb, sqlite or file (SIGNATURE_STORE)
+    #[arg(long, global = true)]
+    signature_store: Option<String>,
+    /// Path of the sqlite or file signature store (SIGNATURE_STORE_PATH)
+    #[arg(long, global = true)]
+    signature_store_path: Option<String>,
+    /// Ed25519 key signing the signature records (SIGNING_KEY_PATH)
+    #[arg(long, global = true)]
+    signing_key_path: Option<String>,
+    /// Key derivation parameters of the passphrase (ENCRYPTION_KDF_PATH)
+    #[arg(long, global = true)]
+    encryption_kdf_path: Option<String>,
+    /// Files hashed at once, defaults to the number of CPUs (VERIFY_WORKERS)
+    #[arg(long, global = true)]
+    verify_workers: Option<usize>,
+    /// Accept signature records written before signing was enabled (ALLOW_UNSIGNED_SIGNATURES)
+    #[arg(long, global = true)]
+    allow_unsigned_signatures: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Record a baseline signature for every file not tracked yet
+    Init,
+    /// Check files against their signatures, every file when no path is given
+    Verify {
+        /// Relative paths or globs to check
+        paths: Vec<String>,
+        /// Rewrite corrupted chunks from parity, the replica or the vault
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Verify every file, then back up and generate parity for the intact ones
+    Backup {
+        /// Rewrite corrupted chunks before backing up
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Check every vault copy against its manifest
+    Scrub,
+    /// Restore files from the vault, every tracked file when no path is given
+    Restore {
+        /// Relative paths to restore
+        paths: Vec<String>,
+        /// Restore under this directory instead of in place
+        #[arg(long)]
+        to: Option<String>,
+        /// Restore the signature versions current at this RFC 3339 time
+        #[arg(long)]
+        at: Option<String>,
+        /// Only list what would be restored
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Record the current content of files as a new signature version
+    Accept {
+        /// Relative paths or globs whose change is intended
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Why the change is intended, kept with the version
+        #[arg(long)]
+        reason: String,
+        /// Who accepts the change, defaults to ACCEPT_AUTHOR or USER
+        #[arg(long)]
+        author: Option<String>,
+        /// Replace a signature record that failed verification
+        #[arg(long)]
+        override_tampered: bool,
+    },
+    /// Drop the signature versions and catalog entry of files removed from storage
+    Forget {
+        /// Relative paths of the removed files
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Show the catalog entry and signature versions of one file
+    Status {
+        /// Relative path of the file
+        file: String,
+    },
+    /// List the catalog of every tracked file
+    Report {
+        /// Only list files whose last check did not pass
+        #[arg(long)]
+        problems: bool,
+    },
+    /// Keep running and start verify, backup and scrub jobs on cron schedules
+    Daemon {
+        /// Also check files as soon as they change (WATCH)
+        #[arg(long)]
+        watch: bool,
+        /// Cron expression of the verify job (SCHEDULE_VERIFY)
+        #[arg(long)]
+        verify: Option<String>,
+        /// Cron expression of the backup job (SCHEDULE_BACKUP)
+        #[arg(long)]
+        backup: Option<String>,
+        /// Cron expression of the scrub job (SCHEDULE_SCRUB)
+        #[arg(long)]
+        scrub: Option<String>,
+    },
+    /// Keep running and check files as soon as they change
+    Watch {
+        /// Quiet time after the last change before a file is checked (WATCH_DEBOUNCE_MS)
+        #[arg(long)]
+        debounce_ms: Option<u64>,
+        /// Files that must never change, alerted on at once (WATCH_IMMUTABLE)
+        #[arg(long)]
+        immutable: Vec<String>,
+    },
+}
+
+impl Cli {
+    /// The environment variables set on the command line, by name,
+    /// including the daemon schedules and watch settings.
+    pub fn overrides(&self) -> HashMap<&'static str, String> {
+        let mut overrides: HashMap<&'static str, String> = self.global.overrides();
+        match &self.command {
+            Some(Command::Daemon { watch, verify, backup, scrub }) => {
+                let schedules: [(&'static str, Option<&String>); 3] = [
+                    ("SCHEDULE_VERIFY", verify.as_ref()),
+                    ("SCHEDULE_BACKUP", backup.as_ref()),
+                    ("SCHEDULE_SCRUB", scrub.as_ref()),
+                ];
+                overrides.extend(schedules
+                    .into_iter()
+                    .filter_map(|(name, value)| value.map(|value| (name, value.clone()))));
+                if *watch {
+                    overrides.insert("WATCH", "true".to_string());
+                }
+            }
+            Some(Command::Watch { debounce_ms, immutable }) => {
+                overrides.insert("WATCH", "true".to_string());
+                // The schedules stay off unless the daemon is asked for them.
+                for name in ["SCHEDULE_VERIFY", "SCHEDULE_BACKUP", "SCHEDULE_SCRUB"] {
+                    overrides.insert(name, String::new());
+                }
+                if let Some(debounce_ms) = debounce_ms {
+                    overrides.insert("WATCH_DEBOUNCE_MS", debounce_ms.to_string());
+                }
+                if !immutable.is_empty() {
+                    overrides.insert("WATCH_IMMUTABLE", immutable.join(","));
+                }
+            }
+            _ => {}
+        }
+
+        overrides
+    }
+
+    /// Whether the command keeps running rather than doing a single task.
+    pub fn is_daemon(&self) -> bool {
+        matches!(self.command, Some(Command::Daemon { .. } | Command::Watch { .. }))
+    }
+}
+
+impl GlobalArgs {
+    fn overrides(&self) -> HashMap<&'static str, String> {
+        let values: [(&'static str, Option<&String>); 9] = [
+            ("ENV_FILE", self.env_file.as_ref()),
+            ("STORAGE_DIRECTORY", self.storage_directory.as_ref()),
+            ("VAULT_DIRECTORY", self.vault_directory.as_ref()),
+            ("PARITY_DIRECTORY", self.parity_directory.as_ref()),
+            ("REPLICA_DIRECTORY", self.replica_directory.as_ref()),
+            ("SIGNATURE_STORE", self.signature_store.as_ref()),
+            ("SIGNATURE_STORE_PATH", self.signature_store_path.as_ref()),
+            ("SIGNING_KEY_PATH", self.signing_key_path.as_ref()),
+            ("ENCRYPTION_KDF_PATH", self.encryption_kdf_path.as_ref()),
+        ];
+        let mut overrides: HashMap<&'static str, String> = values
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name, value.clone())))
+            .collect();
+        if let Some(verify_workers) = self.verify_workers {
+            overrides.insert("VERIFY_WORKERS", verify_workers.to_string());
+        }
+        if self.allow_unsigned_signatures {
+            overrides.insert("ALLOW_UNSIGNED_SIGNATURES", "true".to_string());
+        }
+
+        overrides
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::environment::Environment;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// `ENV_FILE` written for one test, removed once it is over.
+    struct TestEnvFile(PathBuf);
+
+    impl TestEnvFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let path: PathBuf = std::env::temp_dir().join(format!("glacier-cli-{}-{}.env", name, std::process::id()));
+            fs::write(&path, contents).unwrap();
+
+            Self(path)
+        }
+
+        fn path(&self) -> String {
+            self.0.to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for TestEnvFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("agent").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn global_flags_take_precedence_over_the_environment() {
+        let env_file: TestEnvFile = TestEnvFile::new(
+            "precedence",
+            "STORAGE_DIRECTORY=/from-file\nENCRYPTION_KEY=passphrase:from the file\nVAULT_DIRECTORY=/vault-from-file\nSIGNATURE_STORE=sqlite\nVERIFY_WORKERS=2\n"
+        );
+        let cli: Cli = parse(&[
+            "verify",
+            "--env-file", &env_file.path(),
+            "--storage-directory", "/from-flag",
+            "--signature-store", "file",
+            "--verify-workers", "7",
+            "--allow-unsigned-signatures",
+        ]);
+        let environment: Environment = Environment::new(&cli.overrides()).unwrap();
+
+        assert_eq!(environment.storage_directory, "/from-flag");
+        assert_eq!(environment.signature_store, "file");
+        assert_eq!(environment.verify_workers, 7);
+        assert!(environment.allow_unsigned_signatures);
+        assert_eq!(environment.vault_directory.as_deref(), Some("/vault-from-file"));
+        assert_eq!(environment.encryption_key, "passphrase:from the file");
+    }
+
+    #[test]
+    fn only_flags_given_are_overrides() {
+        let overrides: HashMap<&'static str, String> = parse(&["status", "a.bin"]).overrides();
+
+        assert!(overrides.is_empty());
+    }
+
+    #[test]
+    fn watch_turns_the_schedules_off() {
+        let overrides: HashMap<&'static str, String> = parse(&["watch", "--debounce-ms", "500", "--immutable", "*.iso", "--immutable", "keys/*"]).overrides();
+
+        assert_eq!(overrides.get("WATCH").map(String::as_str), Some("true"));
+        assert_eq!(overrides.get("WATCH_DEBOUNCE_MS").map(String::as_str), Some("500"));
+        assert_eq!(overrides.get("WATCH_IMMUTABLE").map(String::as_str), Some("*.iso,keys/*"));
+        for name in ["SCHEDULE_VERIFY", "SCHEDULE_BACKUP", "SCHEDULE_SCRUB"] {
+            assert_eq!(overrides.get(name).map(String::as_str), Some(""));
+        }
+
+        let overrides: HashMap<&'static str, String> = parse(&["daemon", "--backup", "0 3 * * *"]).overrides();
+        assert_eq!(overrides.get("SCHEDULE_BACKUP").map(String::as_str), Some("0 3 * * *"));
+        assert!(!overrides.contains_key("SCHEDULE_VERIFY"));
+        assert!(!overrides.contains_key("WATCH"));
+    }
+}
@@ -1,35 +1,269 @@
+use std::collections::HashMap;
 use std::env;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::thread;
+
+use crate::utils::constants::{
+    ENCRYPTION_KDF_PATH,
+    SIGNATURE_STORE_FILE,
+    SIGNATURE_STORE_FILE_PATH,
+    SIGNATURE_STORE_MONGODB,
+    SIGNATURE_STORE_SQLITE_PATH,
+    SIGNING_KEY_PATH,
+    VAULT_COLLECT_GRACE_HOURS,
+    WATCH_DEBOUNCE_MS,
+    WATCH_SETTLE_SECONDS
+};
 
 pub struct Environment {
     pub storage_directory: String,
     pub encryption_key: String,
+    pub encryption_kdf_path: String,
+    pub previous_encryption_key: Option<String>,
+    pub rotate_key: bool,
+    pub vault_directory: Option<String>,
+    pub parity_directory: Option<String>,
+    pub collect_garbage: bool,
+    pub collect_grace_hours: u64,
+    pub collect_dry_run: bool,
+    pub restore_files: Option<Vec<String>>,
+    pub restore_directory: Option<String>,
+    pub restore_at: Option<String>,
+    pub restore_dry_run: bool,
+    pub accept_files: Option<Vec<String>>,
+    pub accept_reason: Option<String>,
+    pub accept_author: Option<String>,
+    pub accept_override_tampered: bool,
+    pub repair_corrupted: bool,
+    pub replica_directory: Option<String>,
+    pub verify_workers: usize,
+    pub schedule_verify: Option<String>,
+    pub schedule_backup: Option<String>,
+    pub schedule_scrub: Option<String>,
+    pub watch: bool,
+    pub watch_debounce_ms: u64,
+    pub watch_settle_seconds: u64,
+    pub watch_immutable: Vec<String>,
+    pub signature_store: String,
+    pub signature_store_path: String,
+    pub signing_key_path: String,
+    pub allow_unsigned_signatures: bool,
     pub database_url: String,
     pub database_name: String,
+    #[allow(dead_code)]
     pub database_collection: String,
 }
 
 impl Environment {
-    pub fn new() -> Result<Self, env::VarError> {
-        let storage_directory = env::var("STORAGE_DIRECTORY")?;
-        let encryption_key = env::var("ENCRYPTION_KEY")?;
-        let database_user = env::var("DATABASE_USER")?;
-        let database_password = env::var("DATABASE_PASSWORD")?;
-        let database_host = env::var("DATABASE_HOST")?;
-        let database_port = env::var("DATABASE_PORT")?;
-        let database_name = env::var("DATABASE_NAME")?;
-        let database_collection = env::var("DATABASE_COLLECTION")?;
-        
-        let database_url = format!(
-            "mongodb://{}:{}@{}:{}/{}?authSource=admin",
-            database_user, database_password, database_host, database_port, database_name
-        );
+    /// Reads the configuration from the environment, taking the values in
+    /// `overrides` first, then those of the `ENV_FILE` file, instead of
+    /// the variables of the same name. The file is read again on every
+    /// call, which is what lets the daemon reload its configuration.
+    pub fn new(overrides: &HashMap<&'static str, String>) -> Result<Self, String> {
+        let env_file: HashMap<String, String> = match overrides.get("ENV_FILE").cloned().or_else(|| env::var("ENV_FILE").ok()) {
+            Some(path) if !path.is_empty() => Self::read_env_file(&path)?,
+            _ => HashMap::new(),
+        };
+        let var = |name: &str| -> Result<String, String> {
+            match overrides.get(name).or_else(|| env_file.get(name)) {
+                Some(value) => Ok(value.clone()),
+                None => env::var(name).map_err(|e| format!("{}: {}", name, e)),
+            }
+        };
+        let storage_directory = var("STORAGE_DIRECTORY")?;
+        let encryption_key = var("ENCRYPTION_KEY")?;
+        let encryption_kdf_path = var("ENCRYPTION_KDF_PATH")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .unwrap_or_else(|| ENCRYPTION_KDF_PATH.to_string());
+        let previous_encryption_key = var("ENCRYPTION_KEY_PREVIOUS")
+            .ok()
+            .filter(|key| !key.is_empty());
+        let rotate_key = var("ROTATE_KEY")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let vault_directory = var("VAULT_DIRECTORY")
+            .ok()
+            .filter(|path| !path.is_empty());
+        let parity_directory = var("PARITY_DIRECTORY")
+            .ok()
+            .filter(|path| !path.is_empty());
+        let collect_garbage = var("COLLECT_GARBAGE")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let collect_grace_hours = var("COLLECT_GRACE_HOURS")
+            .ok()
+            .and_then(|hours| hours.trim().parse().ok())
+            .unwrap_or(VAULT_COLLECT_GRACE_HOURS);
+        let collect_dry_run = var("COLLECT_DRY_RUN")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let restore_files = var("RESTORE_FILES")
+            .ok()
+            .filter(|files| !files.is_empty())
+            .map(|files| {
+                files.split(',')
+                    .map(|file| file.trim().to_string())
+                    .filter(|file| !file.is_empty() && file != "*")
+                    .collect()
+            });
+        let restore_directory = var("RESTORE_DIRECTORY")
+            .ok()
+            .filter(|path| !path.is_empty());
+        let restore_at = var("RESTORE_AT")
+            .ok()
+            .filter(|at| !at.is_empty());
+        let restore_dry_run = var("RESTORE_DRY_RUN")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let accept_files = var("ACCEPT_FILES")
+            .ok()
+            .filter(|files| !files.is_empty())
+            .map(|files| {
+                files.split(',')
+                    .map(|file| file.trim().to_string())
+                    .filter(|file| !file.is_empty())
+                    .collect()
+            });
+        let accept_reason = var("ACCEPT_REASON")
+            .ok()
+            .map(|reason| reason.trim().to_string())
+            .filter(|reason| !reason.is_empty());
+        let accept_author = var("ACCEPT_AUTHOR")
+            .or_else(|_| var("USER"))
+            .ok()
+            .map(|author| author.trim().to_string())
+            .filter(|author| !author.is_empty());
+        let accept_override_tampered = var("ACCEPT_OVERRIDE_TAMPERED")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let repair_corrupted = var("REPAIR_CORRUPTED")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let replica_directory = var("REPLICA_DIRECTORY")
+            .ok()
+            .filter(|path| !path.is_empty());
+        let verify_workers = var("VERIFY_WORKERS")
+            .ok()
+            .and_then(|workers| workers.trim().parse().ok())
+            .filter(|workers: &usize| *workers > 0)
+            .unwrap_or_else(|| thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1));
+        let schedule_verify = var("SCHEDULE_VERIFY")
+            .ok()
+            .map(|schedule| schedule.trim().to_string())
+            .filter(|schedule| !schedule.is_empty());
+        let schedule_backup = var("SCHEDULE_BACKUP")
+            .ok()
+            .map(|schedule| schedule.trim().to_string())
+            .filter(|schedule| !schedule.is_empty());
+        let schedule_scrub = var("SCHEDULE_SCRUB")
+            .ok()
+            .map(|schedule| schedule.trim().to_string())
+            .filter(|schedule| !schedule.is_empty());
+        let watch = var("WATCH")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let watch_debounce_ms = var("WATCH_DEBOUNCE_MS")
+            .ok()
+            .and_then(|milliseconds| milliseconds.trim().parse().ok())
+            .unwrap_or(WATCH_DEBOUNCE_MS);
+        let watch_settle_seconds = var("WATCH_SETTLE_SECONDS")
+            .ok()
+            .and_then(|seconds| seconds.trim().parse().ok())
+            .unwrap_or(WATCH_SETTLE_SECONDS);
+        let watch_immutable = var("WATCH_IMMUTABLE")
+            .map(|patterns| {
+                patterns.split(',')
+                    .map(|pattern| pattern.trim().to_string())
+                    .filter(|pattern| !pattern.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let signature_store = var("SIGNATURE_STORE")
+            .unwrap_or_else(|_| SIGNATURE_STORE_MONGODB.to_string());
+        let signature_store_path = var("SIGNATURE_STORE_PATH")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .unwrap_or_else(|| match signature_store.as_str() {
+                SIGNATURE_STORE_FILE => SIGNATURE_STORE_FILE_PATH.to_string(),
+                _ => SIGNATURE_STORE_SQLITE_PATH.to_string(),
+            });
+        let signing_key_path = var("SIGNING_KEY_PATH")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .unwrap_or_else(|| SIGNING_KEY_PATH.to_string());
+        let allow_unsigned_signatures = var("ALLOW_UNSIGNED_SIGNATURES")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+
+        let (database_url, database_name, database_collection) = if signature_store == SIGNATURE_STORE_MONGODB {
+            let database_user = var("DATABASE_USER")?;
+            let database_password = var("DATABASE_PASSWORD")?;
+            let database_host = var("DATABASE_HOST")?;
+            let database_port = var("DATABASE_PORT")?;
+            let database_name = var("DATABASE_NAME")?;
+            let database_collection = var("DATABASE_COLLECTION")?;
+
+            let database_url = format!(
+                "mongodb://{}:{}@{}:{}/{}?authSource=admin",
+                database_user, database_password, database_host, database_port, database_name
+            );
+            (database_url, database_name, database_collection)
+        } else {
+            (String::new(), String::new(), String::new())
+        };
 
         Ok(Self {
             storage_directory,
             encryption_key,
+            encryption_kdf_path,
+            previous_encryption_key,
+            rotate_key,
+            vault_directory,
+            parity_directory,
+            collect_garbage,
+            collect_grace_hours,
+            collect_dry_run,
+            restore_files,
+            restore_directory,
+            restore_at,
+            restore_dry_run,
+            accept_files,
+            accept_reason,
+            accept_author,
+            accept_override_tampered,
+            repair_corrupted,
+            replica_directory,
+            verify_workers,
+            schedule_verify,
+            schedule_backup,
+            schedule_scrub,
+            watch,
+            watch_debounce_ms,
+            watch_settle_seconds,
+            watch_immutable,
+            signature_store,
+            signature_store_path,
+            signing_key_path,
+            allow_unsigned_signatures,
             database_url,
             database_name,
             database_collection,
         })
     }
+
+    /// Parses `NAME=value` lines, skipping blank lines and `#` comments.
+    fn read_env_file(path: &str) -> Result<HashMap<String, String>, String> {
+        let contents: String = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read ENV_FILE {}: {}", path, e))?;
+
+        Ok(contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect())
+    }
 }
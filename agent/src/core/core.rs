@@ -1,49 +1,243 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
 use std::fs;
-use chrono::Local;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+use chrono::{Local, Utc};
 use csv::Writer;
+use futures_util::stream::{self, StreamExt};
+use glob::Pattern;
 use log::{error, info, warn};
 use crate::config::environment::Environment;
-use crate::security::security::SecurityHandler;
-use crate::storage::file_handler::FileHandler;
-use crate::storage::signature_handler::SignatureHandler;
-use crate::utils::constants::REPORT_DIRECTORY;
+use crate::core::outcome::{Outcome, StartupError};
+use crate::core::task::Task;
+use crate::security::security::{SecurityError, SecurityHandler};
+use crate::security::signing_handler::SigningHandler;
+use crate::storage::file_handler::{EntryKind, FileHandler, StorageEntry};
+use crate::storage::parity_handler::{ParityChunkSource, ParityHandler, ParityManifest};
+use crate::storage::repair_handler::{ChunkSource, RepairHandler, ReplicaChunkSource, VaultChunkSource};
+use crate::storage::backup_handler::{BackupError, BackupHandler, BackupResult, RestoreOptions, VaultManifest};
+use crate::storage::chunk_diff::ChunkChange;
+use crate::storage::chunk_store::{CollectOptions, CollectReport};
+use crate::storage::signature_handler::{AcceptOptions, Catalog, LoadError, Signature, SignatureHandler};
+use crate::storage::signature_store::{open_signature_store, SignatureStore};
+use crate::utils::constants::{
+    REPORT_DIRECTORY,
+    SIGNATURE_AUTHOR_AGENT,
+    SIGNATURE_REASON_FIRST_SEEN,
+    SIGNATURE_STORE_FILE,
+    SIGNATURE_STORE_MONGODB,
+    SIGNATURE_STORE_SQLITE
+};
 
 pub struct Core {
     file_handler: FileHandler,
-    signature_handler: SignatureHandler,
-    files_status: HashMap<String, FileStatus>
+    /// Shared with the blocking threads that hash files.
+    signature_handler: Arc<SignatureHandler>,
+    /// Shared with the blocking threads that read and write the vault.
+    backup_handler: Option<Arc<BackupHandler>>,
+    parity_handler: Option<Arc<ParityHandler>>,
+    replica_directory: Option<String>,
+    /// Files checked at once.
+    verify_workers: usize,
+    files_status: BTreeMap<String, FileStatus>,
+    /// Worst problem met outside of any one file's status.
+    outcome: Outcome
 }
 
 pub struct FileStatus {
     status: String,
     signature: String,
+    backup: String,
+    /// Signature version the file was checked against, if any.
+    version: Option<u32>,
+    /// The accepted change that version records, when a person accepted
+    /// it rather than the agent recording it on first sight.
+    change: String,
+    /// How the chunks of a corrupted file differ from the stored ones.
+    chunks: Vec<ChunkChange>,
+}
+
+/// What hashing one file against its stored signature found, before
+/// anything is recorded for it.
+enum Inspection {
+    Passed(Signature),
+    Corrupted { stored: Signature, changes: Vec<ChunkChange> },
+    Failed { signature: String, error: String },
+    Unloadable(LoadError),
+    /// No signature is stored yet; holds the one generated for the file.
+    Untracked((String, Vec<String>, Vec<usize>)),
+    /// No signature is stored although the catalog saw this version.
+    Unrecorded(u32),
+}
+
+impl FileStatus {
+    fn outcome(&self) -> Outcome {
+        let outcome: Outcome = Outcome::of_status(&self.status);
+        if self.backup == "failed" {
+            return outcome.max(Outcome::Infrastructure);
+        }
+
+        outcome
+    }
+}
+
+/// Report status of a vault copy that could not be used: `failed` when the
+/// vault could not be read, so nothing is known about the copy itself.
+fn vault_status(e: &BackupError, failed: &'static str) -> &'static str {
+    match e {
+        BackupError::Security { source: SecurityError::WrongKey(_), .. } => "vault-key-missing",
+        BackupError::Security { source: SecurityError::Io(_), .. } | BackupError::Io(_) => failed,
+        BackupError::Security { .. } | BackupError::Mismatch(_) => "vault-damaged",
+    }
 }
 
 impl Core {
-    pub async fn new() -> Self {
-        let env: Environment = Environment::new().expect("Failed to load environment variables");
+    pub async fn new(env: &Environment) -> Result<Self, StartupError> {
         let file_handler: FileHandler = FileHandler::new(&env.storage_directory);
-        let security_handler: SecurityHandler = SecurityHandler::new(&env.encryption_key);
+        let mut security_handler: SecurityHandler = SecurityHandler::new(&env.encryption_key, &env.encryption_kdf_path, env.rotate_key)
+            .map_err(|e| StartupError::configuration(format!("Invalid encryption key: {}", e)))?;
+        if let Some(previous_encryption_key) = &env.previous_encryption_key {
+            let previous: SecurityHandler = SecurityHandler::new(previous_encryption_key, &env.encryption_kdf_path, false)
+                .map_err(|e| StartupError::configuration(format!("Invalid previous encryption key: {}", e)))?;
+            security_handler = security_handler.with_previous_key(previous);
+        }
+        if ![SIGNATURE_STORE_MONGODB, SIGNATURE_STORE_SQLITE, SIGNATURE_STORE_FILE].contains(&env.signature_store.as_str()) {
+            return Err(StartupError::configuration(format!("Unknown signature store: {}", env.signature_store)));
+        }
+        let signature_store: Box<dyn SignatureStore> = open_signature_store(env)
+            .await
+            .map_err(|e| StartupError::infrastructure(format!("Failed to open signature store: {}", e)))?;
+        let signing_handler: SigningHandler = SigningHandler::load_or_create(&env.signing_key_path)
+            .map_err(|e| StartupError::configuration(format!("Failed to load signing key: {}", e)))?;
         let signature_handler: SignatureHandler = SignatureHandler::new(
-            &env.database_url,
-            &env.database_name
-        ).await;
-
-        Self {
+            signature_store,
+            signing_handler,
+            env.allow_unsigned_signatures
+        );
+        let backup_handler: Option<Arc<BackupHandler>> = env.vault_directory
+            .as_deref()
+            .map(|vault_directory| Arc::new(BackupHandler::new(vault_directory, security_handler)));
+        let parity_handler: Option<Arc<ParityHandler>> = env.parity_directory
+            .as_deref()
+            .map(|parity_directory| Arc::new(ParityHandler::new(parity_directory)));
+        Ok(Self {
             file_handler,
-            signature_handler,
-            files_status: HashMap::new()
-        }
+            signature_handler: Arc::new(signature_handler),
+            backup_handler,
+            parity_handler,
+            replica_directory: env.replica_directory.clone(),
+            verify_workers: env.verify_workers,
+            files_status: BTreeMap::new(),
+            outcome: Outcome::Valid
+        })
     }
 
-    pub async fn run(&mut self) {
+    /// Runs `task` and returns the worst outcome met, which the process
+    /// exits with.
+    pub async fn run(&mut self, task: Task) -> Outcome {
         info!("❄️ Glacier initialized and ready");
-        self.verify_files().await;
+        self.files_status.clear();
+        self.outcome = Outcome::Valid;
+        // Tasks that only look at vault copies leave the catalog of the
+        // files themselves alone, as does a restore that does not write
+        // to the storage directory, so that it never counts as verified.
+        let catalog: bool = match &task {
+            Task::Scrub | Task::RotateKey | Task::Forget { .. } => false,
+            Task::Restore { options, .. } => !options.dry_run && options.target_directory.is_none(),
+            _ => true,
+        };
+        // A watch checks a few files at a time, too often for a report each.
+        let report: bool = !matches!(task, Task::Check { .. });
+        match task {
+            Task::Status { file_name } => {
+                self.show_status(&file_name).await;
+                return self.outcome;
+            }
+            Task::Report { problems_only } => {
+                self.show_report(problems_only).await;
+                return self.outcome;
+            }
+            Task::Init => self.init_files().await,
+            Task::Verify { patterns, repair } => {
+                if patterns.is_empty() {
+                    self.verify_files().await;
+                    self.find_missing_files().await;
+                } else {
+                    self.verify_selected(&patterns).await;
+                }
+                if repair {
+                    self.repair_files().await;
+                }
+            }
+            Task::Backup { repair } => {
+                self.verify_files().await;
+                self.find_missing_files().await;
+                if repair {
+                    self.repair_files().await;
+                }
+                self.backup_files().await;
+                self.generate_parity().await;
+            }
+            Task::Scrub => self.scrub_backups().await,
+            Task::Check { file_names } => self.check_files(file_names).await,
+            Task::Restore { file_names, options } => self.restore_files(file_names, &options).await,
+            Task::Accept { patterns, options } => self.accept_files(&patterns, &options).await,
+            Task::Forget { file_names } => self.forget_files(&file_names).await,
+            Task::RotateKey => self.rotate_keys().await,
+            Task::Collect(options) => self.collect_garbage(&options).await,
+        }
+        if catalog {
+            self.update_catalog().await;
+        }
         self.display_files_status();
-        if let Err(e) = self.save_report() {
-            error!("Failed to save report: {}", e);
+        if report {
+            if let Err(e) = self.save_report() {
+                error!("Failed to save report: {}", e);
+                self.record(Outcome::Infrastructure);
+            }
+        }
+
+        self.files_status
+            .values()
+            .map(FileStatus::outcome)
+            .fold(self.outcome, Outcome::max)
+    }
+
+    fn record(&mut self, outcome: Outcome) {
+        self.outcome = self.outcome.max(outcome);
+    }
+
+    /// Records what this run found about every file it reported on.
+    async fn update_catalog(&mut self) {
+        if self.files_status.is_empty() {
+            return;
+        }
+        let previous: HashMap<String, Catalog> = match self.signature_handler.load_catalog().await {
+            Ok(entries) => entries.into_iter().map(|entry| (entry.file_name.clone(), entry)).collect(),
+            Err(e) => {
+                error!("Failed to load the catalog: {}", e);
+                self.record(Outcome::Infrastructure);
+                return;
+            }
+        };
+        let now: String = Utc::now().to_rfc3339();
+        let entries: Vec<Catalog> = self.files_status
+            .iter()
+            .map(|(file_name, file_status)| Catalog::observe(
+                previous.get(file_name),
+                file_name,
+                &self.file_handler.prepare_file_path(file_name),
+                &file_status.status,
+                file_status.version,
+                &now
+            ))
+            .collect();
+
+        if let Err(e) = self.signature_handler.save_catalog(&entries).await {
+            error!("Failed to update the catalog: {}", e);
+            self.record(Outcome::Infrastructure);
         }
     }
 
@@ -58,10 +252,16 @@ impl Core {
 
         let mut wtr: Writer<fs::File> = Writer::from_path(&file_path)?;
 
-        wtr.write_record(&["file", "status", "signature"])?;
+        wtr.write_record(["file", "status", "signature", "backup", "change", "chunks"])?;
 
         for (key, file_status) in &self.files_status {
-            wtr.write_record(&[key, &file_status.status, &file_status.signature])?;
+            let path: String = self.file_handler.prepare_file_path(key);
+            let chunks: String = file_status.chunks
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+                .join("; ");
+            wtr.write_record([&path, &file_status.status, &file_status.signature, &file_status.backup, &file_status.change, &chunks])?;
         }
 
         wtr.flush()?;
@@ -71,96 +271,1097 @@ impl Core {
     }
 
     async fn verify_files(&mut self) {
-        let files: fs::ReadDir = match fs::read_dir(&self.file_handler.get_storage_dir()) {
-            Ok(files) => files,
+        let file_names: Vec<String> = self.walk_files();
+        self.compare_signatures(file_names).await;
+    }
+
+    async fn verify_selected(&mut self, patterns: &[String]) {
+        let file_names: Vec<String> = match self.file_handler.select_files(patterns) {
+            Ok(file_names) => file_names,
             Err(e) => {
-                error!("Failed to read storage directory: {}", e);
+                error!("Cannot verify files: {}", e);
+                self.record(Outcome::Infrastructure);
                 return;
             }
         };
 
-        for file in files.flatten() {
-            let file_name: String = file.file_name().to_string_lossy().to_string();
+        self.compare_signatures(file_names).await;
+    }
+
+    /// Records a baseline signature for every file that has none yet.
+    /// Tracked files keep theirs; a change to them is recorded by accept.
+    async fn init_files(&mut self) {
+        let tracked_versions: HashMap<String, u32> = self.tracked_versions().await;
+        let mut tracked: usize = 0;
+
+        for file_name in self.walk_files() {
             let path: String = self.file_handler.prepare_file_path(&file_name);
-            self.compare_signatures(path).await;
+            match self.signature_handler.load_signature_with_leaves(&file_name).await {
+                Ok(Some(stored)) => {
+                    info!("File '{}' already tracked at signature version {}", path, stored.version);
+                    tracked += 1;
+                }
+                Ok(None) => match tracked_versions.get(&file_name) {
+                    Some(&version) => self.record_inspection(file_name, Inspection::Unrecorded(version)).await,
+                    None => {
+                        let generated: (String, Vec<String>, Vec<usize>) = self.signature_handler.generate_signature_with_leaves(&path);
+                        self.initialize_file(file_name, generated).await;
+                    }
+                },
+                Err(LoadError::Tampered(e)) => {
+                    error!("Stored signature record of '{}' failed verification: {}", path, e);
+                    self.set_status(file_name, "tampered-signature", String::new());
+                }
+                Err(LoadError::Store(e)) => {
+                    error!("Failed to load signature for '{}': {}", path, e);
+                    self.set_status(file_name, "error", String::new());
+                }
+            }
         }
+        info!("{} files were already tracked", tracked);
     }
 
-    async fn compare_signatures(&mut self, file_path: String) {
-        let file_name = match file_path.split('/').last() {
-            Some(name) => name,
-            None => {
-                error!("Invalid file path: {}", file_path);
+    /// The signature version the catalog last saw for each file. A file
+    /// listed here lost its signature record rather than being new.
+    async fn tracked_versions(&mut self) -> HashMap<String, u32> {
+        match self.signature_handler.tracked_versions().await {
+            Ok(tracked_versions) => tracked_versions,
+            Err(e) => {
+                error!("Failed to load the catalog: {}", e);
+                self.record(Outcome::Infrastructure);
+                HashMap::new()
+            }
+        }
+    }
+
+    /// The regular files under the storage directory. Entries that cannot
+    /// be checked are reported as skipped or in error on the way.
+    fn walk_files(&mut self) -> Vec<String> {
+        let entries: Vec<StorageEntry> = match self.file_handler.walk_storage() {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to read storage directory: {}", e);
+                self.record(Outcome::Infrastructure);
+                return Vec::new();
+            }
+        };
+        let mut file_names: Vec<String> = Vec::new();
+
+        for entry in entries {
+            let path: String = self.file_handler.prepare_file_path(&entry.relative_path);
+            match entry.kind {
+                EntryKind::File => file_names.push(entry.relative_path),
+                EntryKind::Symlink | EntryKind::Special => {
+                    warn!("Skipping '{}': not a regular file", path);
+                    self.set_status(entry.relative_path, "skipped", String::new());
+                }
+                EntryKind::Unreadable(e) => {
+                    error!("Failed to read '{}': {}", path, e);
+                    self.set_status(entry.relative_path, "error", String::new());
+                }
+            }
+        }
+
+        file_names
+    }
+
+    /// Checks the files a watch saw change. A name that is gone is reported
+    /// missing when tracked, together with the tracked files under it in
+    /// case it was a directory moved away.
+    async fn check_files(&mut self, file_names: Vec<String>) {
+        let mut changed: Vec<String> = Vec::new();
+        let mut gone: Vec<String> = Vec::new();
+
+        for file_name in file_names {
+            let path: String = self.file_handler.prepare_file_path(&file_name);
+            match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.is_file() => changed.push(file_name),
+                // A directory moved into place brings files of its own.
+                Ok(metadata) if metadata.is_dir() => {
+                    self.verify_selected(&[format!("{}/**", Pattern::escape(&file_name))]).await;
+                }
+                Ok(_) => {
+                    warn!("Skipping '{}': not a regular file", path);
+                    self.set_status(file_name, "skipped", String::new());
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => gone.push(file_name),
+                Err(e) => {
+                    error!("Failed to read '{}': {}", path, e);
+                    self.set_status(file_name, "error", String::new());
+                }
+            }
+        }
+        self.compare_signatures(changed).await;
+        if !gone.is_empty() {
+            let under: Vec<String> = gone.iter().map(|file_name| format!("{}/", file_name)).collect();
+            self.mark_missing(|file_name| {
+                gone.iter().any(|gone| gone == file_name) || under.iter().any(|under| file_name.starts_with(under))
+            }).await;
+        }
+    }
+
+    async fn find_missing_files(&mut self) {
+        self.mark_missing(|_| true).await;
+    }
+
+    /// Reports the tracked files `selected` picks that are neither in
+    /// storage nor already reported on.
+    async fn mark_missing(&mut self, selected: impl Fn(&str) -> bool) {
+        let file_names: Vec<String> = match self.signature_handler.list_signatures().await {
+            Ok(file_names) => file_names,
+            Err(e) => {
+                error!("Failed to list stored signatures: {}", e);
+                self.record(Outcome::Infrastructure);
                 return;
             }
         };
-        let signature_data = self.signature_handler.load_signature_with_leaves(file_name).await;
+
+        for file_name in file_names.into_iter().filter(|file_name| selected(file_name)) {
+            let path: String = self.file_handler.prepare_file_path(&file_name);
+            // Looked at without following links, as the walk does, so that
+            // a dangling link is reported as skipped by both.
+            if self.files_status.contains_key(&file_name) || fs::symlink_metadata(&path).is_ok() {
+                continue;
+            }
+
+            let signature: String = self.signature_handler
+                .load_signature_with_leaves(&file_name)
+                .await
+                .ok()
+                .flatten()
+                .map(|stored| stored.signature)
+                .unwrap_or_default();
+            error!("File '{}' has a stored signature but is missing from storage", path);
+            self.set_status(file_name, "missing", signature);
+        }
+    }
+
+    /// Checks files against their stored signatures. Up to
+    /// `verify_workers` files are looked up and hashed at once, hashing on
+    /// the blocking thread pool; the results are recorded one at a time,
+    /// in the order of `file_names`.
+    async fn compare_signatures(&mut self, file_names: Vec<String>) {
+        if file_names.is_empty() {
+            return;
+        }
+        let tracked_versions: HashMap<String, u32> = self.tracked_versions().await;
+        let files: Vec<(String, String, Option<u32>)> = file_names
+            .into_iter()
+            .map(|file_name| {
+                let file_path: String = self.file_handler.prepare_file_path(&file_name);
+                let tracked_version: Option<u32> = tracked_versions.get(&file_name).copied();
+                (file_name, file_path, tracked_version)
+            })
+            .collect();
+        let signature_handler: Arc<SignatureHandler> = Arc::clone(&self.signature_handler);
+        let mut inspections = stream::iter(files)
+            .map(|(file_name, file_path, tracked_version)| {
+                let signature_handler: Arc<SignatureHandler> = Arc::clone(&signature_handler);
+                async move {
+                    let inspection: Inspection = Self::inspect_file(signature_handler, &file_name, file_path, tracked_version).await;
+                    (file_name, inspection)
+                }
+            })
+            .buffered(self.verify_workers);
+
+        while let Some((file_name, inspection)) = inspections.next().await {
+            self.record_inspection(file_name, inspection).await;
+        }
+    }
+
+    /// Hashes one file against its stored signature. `tracked_version` is
+    /// the version the catalog last saw, so that a file whose record went
+    /// missing is reported instead of getting a new baseline.
+    async fn inspect_file(signature_handler: Arc<SignatureHandler>, file_name: &str, file_path: String, tracked_version: Option<u32>) -> Inspection {
+        let stored: Signature = match signature_handler.load_signature_with_leaves(file_name).await {
+            Ok(Some(stored)) => stored,
+            Ok(None) => {
+                if let Some(version) = tracked_version {
+                    return Inspection::Unrecorded(version);
+                }
+                let generated: Result<(String, Vec<String>, Vec<usize>), String> = Self::hash_blocking(move || {
+                    signature_handler.generate_signature_with_leaves(&file_path)
+                }).await;
+                return Inspection::Untracked(generated.unwrap_or_default());
+            }
+            Err(e) => return Inspection::Unloadable(e),
+        };
+
+        Self::hash_blocking(move || match signature_handler.check_broken_chunks(&file_path, &stored) {
+            Ok(changes) if changes.is_empty() => Inspection::Passed(stored),
+            Ok(changes) => Inspection::Corrupted { stored, changes },
+            Err(error) => Inspection::Failed { signature: stored.signature, error },
+        })
+        .await
+        .unwrap_or_else(|error| Inspection::Failed { signature: String::new(), error })
+    }
+
+    /// Runs `hash` on the blocking thread pool, off the async runtime.
+    async fn hash_blocking<T: Send + 'static>(hash: impl FnOnce() -> T + Send + 'static) -> Result<T, String> {
+        tokio::task::spawn_blocking(hash)
+            .await
+            .map_err(|e| format!("Hashing worker failed: {}", e))
+    }
+
+    /// Runs vault, parity or repair `work` on the blocking thread pool, a
+    /// worker that fails to finish reported as its error.
+    async fn run_blocking<T, E>(work: impl FnOnce() -> Result<T, E> + Send + 'static) -> Result<T, E>
+    where
+        T: Send + 'static,
+        E: From<String> + Send + 'static,
+    {
+        tokio::task::spawn_blocking(work)
+            .await
+            .unwrap_or_else(|e| Err(E::from(format!("Worker failed: {}", e))))
+    }
+
+    async fn record_inspection(&mut self, file_name: String, inspection: Inspection) {
+        let file_path: String = self.file_handler.prepare_file_path(&file_name);
+
+        match inspection {
+            Inspection::Passed(stored) => {
+                info!("File '{}' integrity check passed", file_path);
+                self.set_status(file_name.clone(), "valid", stored.signature.clone());
+                self.set_version(&file_name, &stored);
+            }
+            Inspection::Corrupted { stored, changes } => {
+                error!("File '{}' has {} changed chunks", file_path, changes.len());
+                for change in &changes {
+                    error!("File '{}' chunk {}", file_path, change);
+                }
+                self.set_status(file_name.clone(), "corrupted", stored.signature.clone());
+                self.set_version(&file_name, &stored);
+                if let Some(file_status) = self.files_status.get_mut(&file_name) {
+                    file_status.chunks = changes;
+                }
+            }
+            Inspection::Failed { signature, error } => {
+                error!("File integrity check failed for '{}': {}", file_path, error);
+                self.set_status(file_name, "error", signature);
+            }
+            Inspection::Unloadable(LoadError::Tampered(e)) => {
+                error!("Refusing to verify '{}' against a tampered signature record: {}", file_path, e);
+                self.set_status(file_name, "tampered-signature", String::new());
+            }
+            Inspection::Unloadable(LoadError::Store(e)) => {
+                error!("Failed to load signature for '{}': {}", file_path, e);
+                self.set_status(file_name, "error", String::new());
+            }
+            Inspection::Unrecorded(version) => {
+                error!(
+                    "File '{}' was tracked at signature version {} but its signature record is gone, refusing to record a new baseline",
+                    file_path, version
+                );
+                self.set_status(file_name, "missing-signature", String::new());
+            }
+            Inspection::Untracked(generated) => self.initialize_file(file_name, generated).await,
+        }
+    }
+
+    /// Saves the first signature of a file, generated beforehand.
+    async fn initialize_file(&mut self, file_name: String, generated: (String, Vec<String>, Vec<usize>)) {
+        let file_path: String = self.file_handler.prepare_file_path(&file_name);
+        let (generated_signature, generated_leaves, chunk_positions) = generated;
         
-        match signature_data {
-            Some((original_signature, original_leaves, chunk_positions)) => {
-                match self.signature_handler.check_broken_chunks(&file_path, &original_signature, &original_leaves, Some(&chunk_positions)) {
-                    Ok(corrupted_chunks) if corrupted_chunks.is_empty() => {
-                        info!("File '{}' integrity check passed", file_path);
-                        self.files_status.insert(file_path, FileStatus {
-                            status: "valid".to_string(),
-                            signature: original_signature
-                        });
-                    }
-                    Ok(corrupted_chunks) => {
-                        error!(
-                            "File '{}' has corrupted chunks: {:?}",
-                            file_path, corrupted_chunks
-                        );
-                        self.files_status.insert(file_path, FileStatus {
-                            status: "corrupted".to_string(),
-                            signature: original_signature
-                        });
-                    }
-                    Err(e) => {
-                        error!("File integrity check failed for '{}': {}", file_path, e);
-                        self.files_status.insert(file_path, FileStatus {
-                            status: "error".to_string(),
-                            signature: original_signature
-                        });
+        if generated_signature.is_empty() {
+            error!("Failed to generate signature for {}", file_path);
+            self.set_status(file_name, "error", String::new());
+            return;
+        }
+
+        let saved: Signature = match self.signature_handler.save_signature(
+            &file_name, 
+            &generated_signature, 
+            &generated_leaves,
+            &chunk_positions,
+            SIGNATURE_AUTHOR_AGENT,
+            SIGNATURE_REASON_FIRST_SEEN
+        ).await {
+            Ok(saved) => saved,
+            Err(e) => {
+                error!("Failed to save signature for {}: {}", file_path, e);
+                self.set_status(file_name, "error", String::new());
+                return;
+            }
+        };
+        info!("Saved signature with {} chunks for {}", generated_leaves.len(), file_path);
+
+        self.set_status(file_name.clone(), "initialized", generated_signature);
+        self.set_version(&file_name, &saved);
+    }
+
+    fn set_status(&mut self, file_name: String, status: &str, signature: String) {
+        self.files_status.insert(file_name, FileStatus {
+            status: status.to_string(),
+            signature,
+            backup: String::new(),
+            version: None,
+            change: String::new(),
+            chunks: Vec::new()
+        });
+    }
+
+    fn set_version(&mut self, file_name: &str, stored: &Signature) {
+        if let Some(file_status) = self.files_status.get_mut(file_name) {
+            file_status.version = Some(stored.version);
+            file_status.change = Self::describe_change(stored);
+        }
+    }
+
+    /// Points the report at the accepted change a signature version
+    /// records. Versions the agent recorded itself have nothing to show.
+    fn describe_change(stored: &Signature) -> String {
+        match &stored.author {
+            Some(author) if author != SIGNATURE_AUTHOR_AGENT => format!(
+                "version {} accepted by {} at {}: {}",
+                stored.version,
+                author,
+                stored.created_at.as_deref().unwrap_or_default(),
+                stored.reason.as_deref().unwrap_or_default()
+            ),
+            _ => String::new(),
+        }
+    }
+
+    async fn backup_files(&mut self) {
+        let backup_handler: Arc<BackupHandler> = match &self.backup_handler {
+            Some(backup_handler) => Arc::clone(backup_handler),
+            None => return,
+        };
+
+        for (file_name, file_status) in self.files_status.iter_mut() {
+            if !["valid", "initialized", "repaired"].contains(&file_status.status.as_str()) {
+                continue;
+            }
+            let stored: Signature = match self.signature_handler.load_signature_with_leaves(file_name).await {
+                Ok(Some(stored)) => stored,
+                _ => continue,
+            };
+
+            let loaded: BackupResult<Option<VaultManifest>> = Self::run_blocking({
+                let backup_handler: Arc<BackupHandler> = Arc::clone(&backup_handler);
+                let file_name: String = file_name.clone();
+                move || backup_handler.load_manifest(&file_name)
+            }).await;
+            match loaded {
+                Ok(Some(manifest)) if manifest.signature == stored.signature => {
+                    let verified: BackupResult<()> = Self::run_blocking({
+                        let backup_handler: Arc<BackupHandler> = Arc::clone(&backup_handler);
+                        move || backup_handler.verify_backup(&manifest)
+                    }).await;
+                    match verified {
+                        Ok(()) => {
+                            if backup_handler.needs_rotation() {
+                                warn!("Vault copy of '{}' is not under the current encryption key, run with ROTATE_KEY", file_name);
+                            }
+                            file_status.backup = "verified".to_string();
+                            continue;
+                        }
+                        Err(e) => warn!("Vault copy of '{}' is damaged, backing it up again: {}", file_name, e),
                     }
                 }
-            },
+                Ok(_) => {}
+                Err(e) => warn!("Failed to load vault manifest for '{}': {}", file_name, e),
+            }
+
+            let path: String = self.file_handler.prepare_file_path(file_name);
+            let backed_up: BackupResult<VaultManifest> = Self::run_blocking({
+                let backup_handler: Arc<BackupHandler> = Arc::clone(&backup_handler);
+                let file_name: String = file_name.clone();
+                let path: String = path.clone();
+                move || backup_handler.backup_file(&file_name, &path, &stored)
+            }).await;
+            match backed_up {
+                Ok(_) => file_status.backup = "stored".to_string(),
+                Err(e) => {
+                    error!("Failed to back up '{}': {}", path, e);
+                    file_status.backup = "failed".to_string();
+                }
+            }
+        }
+    }
+
+    async fn generate_parity(&mut self) {
+        let parity_handler: Arc<ParityHandler> = match &self.parity_handler {
+            Some(parity_handler) => Arc::clone(parity_handler),
+            None => return,
+        };
+        let mut failed: bool = false;
+
+        for (file_name, file_status) in &self.files_status {
+            if !["valid", "initialized", "repaired"].contains(&file_status.status.as_str()) {
+                continue;
+            }
+            let loaded: Result<Option<ParityManifest>, String> = Self::run_blocking({
+                let parity_handler: Arc<ParityHandler> = Arc::clone(&parity_handler);
+                let file_name: String = file_name.clone();
+                move || parity_handler.load_manifest(&file_name)
+            }).await;
+            match loaded {
+                Ok(Some(manifest)) if manifest.signature == file_status.signature => continue,
+                Ok(_) => {}
+                Err(e) => warn!("Failed to load parity manifest for '{}': {}", file_name, e),
+            }
+            let stored: Signature = match self.signature_handler.load_signature_with_leaves(file_name).await {
+                Ok(Some(stored)) => stored,
+                _ => continue,
+            };
+
+            let path: String = self.file_handler.prepare_file_path(file_name);
+            let generated: Result<ParityManifest, String> = Self::run_blocking({
+                let parity_handler: Arc<ParityHandler> = Arc::clone(&parity_handler);
+                let file_name: String = file_name.clone();
+                let path: String = path.clone();
+                move || parity_handler.generate(&file_name, &path, &stored)
+            }).await;
+            if let Err(e) = generated {
+                error!("Failed to generate parity for '{}': {}", path, e);
+                failed = true;
+            }
+        }
+        if failed {
+            self.record(Outcome::Infrastructure);
+        }
+    }
+
+    async fn repair_files(&mut self) {
+        let file_names: Vec<String> = self.files_status
+            .iter()
+            .filter(|(_, file_status)| file_status.status == "corrupted")
+            .map(|(file_name, _)| file_name.clone())
+            .collect();
+
+        for file_name in file_names {
+            if self.repair_file(&file_name).await {
+                if let Some(file_status) = self.files_status.get_mut(&file_name) {
+                    file_status.status = "repaired".to_string();
+                }
+            }
+        }
+    }
+
+    /// Replaces the corrupted chunks of a file from its parity first, then
+    /// from the replica directory and the vault, and reports whether the
+    /// file was repaired.
+    async fn repair_file(&self, file_name: &str) -> bool {
+        let path: String = self.file_handler.prepare_file_path(file_name);
+        let stored: Signature = match self.signature_handler.load_signature_with_leaves(file_name).await {
+            Ok(Some(stored)) => stored,
+            _ => return false,
+        };
+        let signature_handler: Arc<SignatureHandler> = Arc::clone(&self.signature_handler);
+        let parity_handler: Option<Arc<ParityHandler>> = self.parity_handler.clone();
+        let backup_handler: Option<Arc<BackupHandler>> = self.backup_handler.clone();
+        let replica_path: Option<String> = self.replica_directory
+            .as_ref()
+            .map(|replica_directory| format!("{}/{}", replica_directory, file_name));
+        let file_name: String = file_name.to_string();
+        let repair_path: String = path.clone();
+
+        Self::hash_blocking(move || {
+            Self::repair_chunks(&signature_handler, &file_name, &repair_path, &stored, parity_handler.as_deref(), backup_handler.as_deref(), replica_path)
+        })
+        .await
+        .unwrap_or_else(|e| {
+            error!("Failed to repair '{}': {}", path, e);
+            false
+        })
+    }
+
+    /// Finds the corrupted chunks of a file and rewrites them from the
+    /// first source that has them, on a blocking thread.
+    fn repair_chunks(
+        signature_handler: &SignatureHandler,
+        file_name: &str,
+        path: &str,
+        stored: &Signature,
+        parity_handler: Option<&ParityHandler>,
+        backup_handler: Option<&BackupHandler>,
+        replica_path: Option<String>
+    ) -> bool {
+        // Every stored chunk whose place now holds other bytes, whether it
+        // was modified, removed or moved away.
+        let corrupted_chunks: Vec<usize> = match signature_handler.check_broken_chunks(path, stored) {
+            Ok(changes) => changes.iter().filter_map(|change| change.original_index).collect(),
+            Err(e) => {
+                error!("Cannot repair '{}': {}", path, e);
+                return false;
+            }
+        };
+
+        let parity: Option<ParityChunkSource> = parity_handler.and_then(|parity_handler| {
+            match parity_handler.load_manifest(file_name) {
+                Ok(Some(manifest)) if manifest.signature == stored.signature => Some(ParityChunkSource {
+                    parity_handler,
+                    manifest,
+                    file_path: path.to_string(),
+                    stored,
+                }),
+                _ => None,
+            }
+        });
+        let replica: Option<ReplicaChunkSource> = replica_path.map(|replica_path| {
+            ReplicaChunkSource {
+                path: replica_path,
+                chunk_positions: stored.chunk_positions.clone(),
+            }
+        });
+        let vault: Option<VaultChunkSource> = backup_handler.and_then(|backup_handler| {
+            match backup_handler.load_manifest(file_name) {
+                Ok(Some(manifest)) if manifest.signature == stored.signature => {
+                    Some(VaultChunkSource { backup_handler, manifest })
+                }
+                _ => None,
+            }
+        });
+        let mut sources: Vec<&dyn ChunkSource> = Vec::new();
+        if let Some(parity) = parity.as_ref() {
+            sources.push(parity);
+        }
+        if let Some(replica) = replica.as_ref() {
+            sources.push(replica);
+        }
+        if let Some(vault) = vault.as_ref() {
+            sources.push(vault);
+        }
+        if sources.is_empty() {
+            error!("Cannot repair '{}': no parity, replica or vault copy available", path);
+            return false;
+        }
+
+        match RepairHandler::repair_file(path, stored, &corrupted_chunks, &sources) {
+            Ok(_) => true,
+            Err(e) => {
+                error!("Failed to repair '{}': {}", path, e);
+                false
+            }
+        }
+    }
+
+    /// Rewraps the data key of every vault copy still under a previous key.
+    async fn rotate_keys(&mut self) {
+        let file_names: Vec<String> = match self.signature_handler.list_signatures().await {
+            Ok(file_names) => file_names,
+            Err(e) => {
+                error!("Failed to list stored signatures: {}", e);
+                self.record(Outcome::Infrastructure);
+                return;
+            }
+        };
+        let backup_handler: &BackupHandler = match &self.backup_handler {
+            Some(backup_handler) => backup_handler,
             None => {
-                let (generated_signature, generated_leaves, chunk_positions) = 
-                    self.signature_handler.generate_signature_with_leaves(&file_path);
-                
-                if generated_signature.is_empty() {
-                    error!("Failed to generate signature for {}", file_path);
-                    return;
+                error!("Rotating the encryption key needs VAULT_DIRECTORY to be set");
+                self.record(Outcome::Configuration);
+                return;
+            }
+        };
+
+        let mut statuses: Vec<(String, &str, String)> = Vec::new();
+        for file_name in file_names {
+            let manifest: VaultManifest = match backup_handler.load_manifest(&file_name) {
+                Ok(Some(manifest)) => manifest,
+                Ok(None) => continue,
+                Err(e) => {
+                    error!("Cannot rotate the key of '{}': {}", file_name, e);
+                    statuses.push((file_name, vault_status(&e, "rotate-failed"), String::new()));
+                    continue;
                 }
-    
-                if let Err(e) = self.signature_handler.save_signature(
-                    file_name, 
-                    &generated_signature, 
-                    &generated_leaves,
-                    &chunk_positions
-                ).await {
-                    error!("Failed to save signature for {}: {}", file_path, e);
+            };
+            let status: &str = match backup_handler.rotate_key() {
+                Ok(true) => "rotated",
+                Ok(false) => "key-current",
+                Err(e) => {
+                    error!("Cannot rotate the key of '{}': {}", file_name, e);
+                    vault_status(&e, "rotate-failed")
+                }
+            };
+            statuses.push((file_name, status, manifest.signature));
+        }
+        for (file_name, status, signature) in statuses {
+            self.set_status(file_name, status, signature);
+        }
+    }
+
+    /// Checks the vault copy of every tracked file against its manifest,
+    /// without reading the files themselves.
+    async fn scrub_backups(&mut self) {
+        let file_names: Vec<String> = match self.signature_handler.list_signatures().await {
+            Ok(file_names) => file_names,
+            Err(e) => {
+                error!("Failed to list stored signatures: {}", e);
+                self.record(Outcome::Infrastructure);
+                return;
+            }
+        };
+        let backup_handler: &BackupHandler = match &self.backup_handler {
+            Some(backup_handler) => backup_handler,
+            None => {
+                error!("Scrubbing the vault needs VAULT_DIRECTORY to be set");
+                self.record(Outcome::Configuration);
+                return;
+            }
+        };
+
+        let mut statuses: Vec<(String, &str, String)> = Vec::new();
+        for file_name in file_names {
+            let manifest: VaultManifest = match backup_handler.load_manifest(&file_name) {
+                Ok(Some(manifest)) => manifest,
+                Ok(None) => continue,
+                Err(e) => {
+                    error!("Cannot scrub the vault copy of '{}': {}", file_name, e);
+                    statuses.push((file_name, vault_status(&e, "error"), String::new()));
+                    continue;
+                }
+            };
+            match backup_handler.verify_backup(&manifest) {
+                Ok(()) => statuses.push((file_name, "scrubbed", manifest.signature)),
+                Err(e) => {
+                    error!("Cannot verify the vault copy of '{}': {}", file_name, e);
+                    statuses.push((file_name, vault_status(&e, "error"), manifest.signature));
+                }
+            }
+        }
+        for (file_name, status, signature) in statuses {
+            let backup: &str = match status {
+                "scrubbed" => "verified",
+                "vault-damaged" => "damaged",
+                _ => "",
+            };
+            self.set_status(file_name.clone(), status, signature);
+            if let Some(file_status) = self.files_status.get_mut(&file_name) {
+                file_status.backup = backup.to_string();
+            }
+        }
+    }
+
+    /// Records the current content of every file matching the requested
+    /// patterns as a new signature version, so that an intended change is
+    /// no longer reported as corruption.
+    async fn accept_files(&mut self, patterns: &[String], options: &AcceptOptions) {
+        let file_names: Vec<String> = match self.file_handler.select_files(patterns) {
+            Ok(file_names) => file_names,
+            Err(e) => {
+                error!("Cannot accept changes: {}", e);
+                self.record(Outcome::Infrastructure);
+                return;
+            }
+        };
+
+        for file_name in file_names {
+            let (status, signature, version) = self.accept_file(&file_name, options).await;
+            self.set_status(file_name.clone(), status, signature);
+            if let Some(version) = version {
+                self.set_version(&file_name, &version);
+            }
+        }
+    }
+
+    /// Accepts one file and returns its report status, signature and the
+    /// signature version now in effect.
+    async fn accept_file(&self, file_name: &str, options: &AcceptOptions) -> (&'static str, String, Option<Signature>) {
+        let path: String = self.file_handler.prepare_file_path(file_name);
+        let mut reason: String = options.reason.clone();
+        let previous: Option<Signature> = match self.signature_handler.load_signature_with_leaves(file_name).await {
+            Ok(previous) => previous,
+            Err(LoadError::Tampered(e)) if options.override_tampered => {
+                warn!("Accepting '{}' over a signature record that failed verification: {}", path, e);
+                reason = format!("{} (overrides a signature record that failed verification: {})", reason, e);
+                None
+            }
+            Err(LoadError::Tampered(e)) => {
+                error!("Cannot accept '{}': its signature record failed verification ({}), pass --override-tampered to replace it", path, e);
+                return ("tampered-signature", String::new(), None);
+            }
+            Err(LoadError::Store(e)) => {
+                error!("Cannot accept '{}': {}", path, e);
+                return ("accept-failed", String::new(), None);
+            }
+        };
+        let generated: Result<(String, Vec<String>, Vec<usize>), String> = Self::hash_blocking({
+            let signature_handler: Arc<SignatureHandler> = Arc::clone(&self.signature_handler);
+            let path: String = path.clone();
+            move || signature_handler.generate_signature_with_leaves(&path)
+        }).await;
+        let (signature, leaves, chunk_positions) = generated.unwrap_or_default();
+        if signature.is_empty() {
+            error!("Cannot accept '{}': failed to generate its signature", path);
+            return ("accept-failed", String::new(), None);
+        }
+        if let Some(previous) = previous.filter(|previous| previous.signature == signature) {
+            info!("File '{}' already matches signature version {}", path, previous.version);
+            return ("valid", signature, Some(previous));
+        }
+
+        match self.signature_handler.save_signature(
+            file_name,
+            &signature,
+            &leaves,
+            &chunk_positions,
+            &options.author,
+            &reason
+        ).await {
+            Ok(saved) => {
+                warn!("Accepted the change of '{}' as signature version {} by {}: {}", path, saved.version, options.author, reason);
+                ("accepted", signature, Some(saved))
+            }
+            Err(e) => {
+                error!("Failed to save the accepted signature of '{}': {}", path, e);
+                ("accept-failed", signature, None)
+            }
+        }
+    }
+
+    /// Drops every signature version and the catalog entry of files that
+    /// were removed from storage on purpose, so that they are no longer
+    /// reported as missing and their vault chunks can be collected.
+    async fn forget_files(&mut self, file_names: &[String]) {
+        for file_name in file_names {
+            let path: String = self.file_handler.prepare_file_path(file_name);
+            if fs::symlink_metadata(&path).is_ok() {
+                error!("Cannot forget '{}': it is still in storage", path);
+                self.record(Outcome::Configuration);
+                continue;
+            }
+
+            match self.signature_handler.forget(file_name).await {
+                Ok(0) => warn!("File '{}' has no stored signature to forget", path),
+                Ok(deleted) => warn!("Forgot {} signature versions of '{}'", deleted, path),
+                Err(e) => {
+                    error!("Failed to forget '{}': {}", path, e);
+                    self.record(Outcome::Infrastructure);
+                }
+            }
+        }
+    }
+
+    /// Removes the vault chunks that no stored signature version and no
+    /// vault manifest refers to. Any failure while listing what is still
+    /// referenced stops the collection before anything is removed.
+    async fn collect_garbage(&mut self, options: &CollectOptions) {
+        let backup_handler: &BackupHandler = match &self.backup_handler {
+            Some(backup_handler) => backup_handler,
+            None => {
+                error!("Collecting vault chunks needs VAULT_DIRECTORY to be set");
+                self.record(Outcome::Configuration);
+                return;
+            }
+        };
+        let collected: Result<CollectReport, String> = match self.live_chunks(backup_handler).await {
+            Ok(live) => backup_handler
+                .collect_garbage(&live, options)
+                .map_err(|e| format!("Failed to collect vault chunks: {}", e)),
+            Err(e) => Err(format!("{}, nothing collected", e)),
+        };
+
+        match collected {
+            Ok(report) => Self::log_collect_report(&report, options.dry_run),
+            Err(e) => {
+                error!("{}", e);
+                self.record(Outcome::Infrastructure);
+            }
+        }
+    }
+
+    /// Leaves of every stored signature version and every vault manifest.
+    async fn live_chunks(&self, backup_handler: &BackupHandler) -> Result<HashSet<String>, String> {
+        let file_names: Vec<String> = self.signature_handler
+            .list_signatures()
+            .await
+            .map_err(|e| format!("Failed to list stored signatures: {}", e))?;
+        let mut live: HashSet<String> = backup_handler
+            .manifest_leaves()
+            .map_err(|e| format!("Failed to read vault manifests: {}", e))?;
+
+        for file_name in &file_names {
+            let history: Vec<Signature> = self.signature_handler
+                .signature_history(file_name)
+                .await
+                .map_err(|e| format!("Failed to load the signature history of '{}': {}", file_name, e))?;
+            live.extend(history.into_iter().flat_map(|signature| signature.leaves));
+        }
+        info!("{} chunks are referenced by {} files", live.len(), file_names.len());
+
+        Ok(live)
+    }
+
+    fn log_collect_report(report: &CollectReport, dry_run: bool) {
+        let action: &str = if dry_run { "Would remove" } else { "Removed" };
+
+        info!(
+            "{} {} unreferenced chunks ({} bytes) and {} stale temporary files, kept {} referenced and {} recent chunks",
+            action,
+            report.removed,
+            report.removed_bytes,
+            report.temporary,
+            report.live,
+            report.recent
+        );
+        if report.restored > 0 {
+            let action: &str = if dry_run { "Found" } else { "Restored" };
+            warn!("{} {} chunks left aside by an interrupted collection", action, report.restored);
+        }
+    }
+
+    /// Restores `file_names` from the vault, or every file with a stored
+    /// signature when the list is empty.
+    async fn restore_files(&mut self, file_names: Vec<String>, options: &RestoreOptions) {
+        if self.backup_handler.is_none() {
+            error!("Restoring needs VAULT_DIRECTORY to be set");
+            self.record(Outcome::Configuration);
+            return;
+        }
+        let file_names: Vec<String> = if file_names.is_empty() {
+            match self.signature_handler.list_signatures().await {
+                Ok(file_names) => file_names,
+                Err(e) => {
+                    error!("Failed to list stored signatures: {}", e);
+                    self.record(Outcome::Infrastructure);
                     return;
-                } else {
-                    info!("Saved signature with {} chunks for {}", generated_leaves.len(), file_path);
                 }
-    
-                self.files_status.insert(file_path, FileStatus {
-                    status: "initialized".to_string(),
-                    signature: generated_signature
-                });
             }
+        } else {
+            file_names
+        };
+
+        for file_name in file_names {
+            let (status, signature) = self.restore_file(&file_name, options).await;
+            self.set_status(file_name, status, signature);
+        }
+    }
+
+    /// Restores one file and returns its report status and signature. A
+    /// file that is already intact in place is left untouched.
+    async fn restore_file(&self, file_name: &str, options: &RestoreOptions) -> (&'static str, String) {
+        let backup_handler: Arc<BackupHandler> = match &self.backup_handler {
+            Some(backup_handler) => Arc::clone(backup_handler),
+            None => return ("restore-failed", String::new()),
+        };
+        let loaded: Result<Option<Signature>, LoadError> = match options.at {
+            Some(at) => self.signature_handler.load_signature_at(file_name, at).await,
+            None => self.signature_handler.load_signature_with_leaves(file_name).await,
+        };
+        let stored: Signature = match loaded {
+            Ok(Some(stored)) => stored,
+            Ok(None) => {
+                match options.at {
+                    Some(at) => error!("Cannot restore '{}': no signature version recorded by {}", file_name, at.to_rfc3339()),
+                    None => error!("Cannot restore '{}': no stored signature", file_name),
+                }
+                return ("restore-failed", String::new());
+            }
+            Err(e) => {
+                error!("Cannot restore '{}': {}", file_name, e);
+                return ("restore-failed", String::new());
+            }
+        };
+        // The manifest only describes the latest copy; other versions are
+        // rebuilt from the chunk store by their leaves.
+        let manifest: BackupResult<VaultManifest> = Self::run_blocking({
+            let backup_handler: Arc<BackupHandler> = Arc::clone(&backup_handler);
+            let file_name: String = file_name.to_string();
+            let stored: Signature = stored.clone();
+            move || match backup_handler.load_manifest(&file_name)? {
+                Some(manifest) if manifest.signature == stored.signature => Ok(manifest),
+                _ => backup_handler.version_manifest(&stored),
+            }
+        }).await;
+        let manifest: VaultManifest = match manifest {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                error!("Cannot restore version {} of '{}': {}", stored.version, file_name, e);
+                return (vault_status(&e, "restore-failed"), stored.signature);
+            }
+        };
+        let target_path: String = match &options.target_directory {
+            Some(target_directory) => format!("{}/{}", target_directory, file_name),
+            None => {
+                let path: String = self.file_handler.prepare_file_path(file_name);
+                let intact = Self::hash_blocking({
+                    let signature_handler: Arc<SignatureHandler> = Arc::clone(&self.signature_handler);
+                    let path: String = path.clone();
+                    let stored: Signature = stored.clone();
+                    move || signature_handler.check_broken_chunks(&path, &stored)
+                }).await;
+                if intact.is_ok_and(|intact| intact.is_ok_and(|changes| changes.is_empty())) {
+                    info!("File '{}' is intact, nothing to restore", path);
+                    return ("valid", stored.signature);
+                }
+                path
+            }
+        };
+
+        if options.dry_run {
+            let size: usize = manifest.chunk_positions.last().copied().unwrap_or(0);
+            info!(
+                "Would restore version {} of '{}' ({} chunks, {} bytes) to {}",
+                stored.version,
+                file_name,
+                manifest.leaves.len(),
+                size,
+                target_path
+            );
+            return ("would-restore", stored.signature);
+        }
+        let restored: BackupResult<u64> = Self::run_blocking({
+            let stored: Signature = stored.clone();
+            move || backup_handler.restore_file(&manifest, &stored, Path::new(&target_path))
+        }).await;
+        match restored {
+            Ok(_) => ("restored", stored.signature),
+            Err(e) => {
+                error!("Failed to restore '{}': {}", file_name, e);
+                (vault_status(&e, "restore-failed"), stored.signature)
+            }
+        }
+    }
+
+    /// Prints what is known about one file: its state now, every
+    /// signature version recorded for it, its catalog entry and vault copy.
+    async fn show_status(&mut self, file_name: &str) {
+        let file_name: &str = file_name.trim_start_matches("./");
+        let path: String = self.file_handler.prepare_file_path(file_name);
+        let mut changes: Vec<ChunkChange> = Vec::new();
+        let (state, detail): (&str, String) = match self.signature_handler.load_signature_with_leaves(file_name).await {
+            Ok(Some(_)) if fs::symlink_metadata(&path).is_err() => ("missing", String::new()),
+            Ok(Some(stored)) => match self.signature_handler.check_broken_chunks(&path, &stored) {
+                Ok(found) if found.is_empty() => ("valid", String::new()),
+                Ok(found) => {
+                    changes = found;
+                    ("corrupted", format!(" ({} changed chunks)", changes.len()))
+                }
+                Err(e) => ("error", format!(" ({})", e)),
+            },
+            Ok(None) => match self.signature_handler.tracked_versions().await {
+                Ok(tracked_versions) => match tracked_versions.get(file_name) {
+                    Some(version) => ("missing-signature", format!(" (catalog last saw version {})", version)),
+                    None => ("untracked", String::new()),
+                },
+                Err(e) => ("error", format!(" ({})", e)),
+            },
+            Err(LoadError::Tampered(e)) => ("tampered-signature", format!(" ({})", e)),
+            Err(LoadError::Store(e)) => ("error", format!(" ({})", e)),
+        };
+        self.record(Outcome::of_status(state));
+        println!("file:      {}", path);
+        println!("state:     {}{}", state, detail);
+        for change in &changes {
+            println!("chunk:     {}", change);
+        }
+
+        match self.signature_handler.signature_history(file_name).await {
+            Ok(history) => {
+                for signature in history.iter().rev() {
+                    println!(
+                        "version {}: {} by {} at {}: {}",
+                        signature.version,
+                        signature.signature,
+                        signature.author.as_deref().unwrap_or("-"),
+                        signature.created_at.as_deref().unwrap_or("-"),
+                        signature.reason.as_deref().unwrap_or("-")
+                    );
+                }
+            }
+            Err(e) => {
+                error!("Failed to load the signature history of '{}': {}", path, e);
+                self.record(Outcome::Infrastructure);
+            }
+        }
+
+        match self.signature_handler.load_catalog().await {
+            Ok(entries) => match entries.into_iter().find(|entry| entry.file_name == file_name) {
+                Some(entry) => {
+                    println!("size:      {}", entry.size.map(|size| size.to_string()).unwrap_or("-".to_string()));
+                    println!("modified:  {}", entry.modified_at.as_deref().unwrap_or("-"));
+                    println!(
+                        "mode:      {} {}:{}",
+                        entry.permissions.as_deref().unwrap_or("-"),
+                        entry.owner_uid.map(|uid| uid.to_string()).unwrap_or("-".to_string()),
+                        entry.owner_gid.map(|gid| gid.to_string()).unwrap_or("-".to_string())
+                    );
+                    println!("seen:      {}", entry.first_seen);
+                    println!("verified:  {}", entry.last_verified.as_deref().unwrap_or("never"));
+                    println!("last run:  {}", entry.last_status);
+                }
+                None => println!("catalog:   no entry"),
+            },
+            Err(e) => {
+                error!("Failed to load the catalog: {}", e);
+                self.record(Outcome::Infrastructure);
+            }
+        }
+
+        if let Some(backup_handler) = &self.backup_handler {
+            match backup_handler.load_manifest(file_name) {
+                Ok(Some(manifest)) => println!("vault:     {} ({} chunks)", manifest.signature, manifest.leaves.len()),
+                Ok(None) => println!("vault:     no copy"),
+                Err(e) => {
+                    error!("Failed to load vault manifest for '{}': {}", path, e);
+                    self.record(Outcome::Infrastructure);
+                }
+            }
+        }
+    }
+
+    /// Prints the catalog, one tracked file per line, optionally only the
+    /// files whose last check did not pass. The outcome is the worst last
+    /// status listed.
+    async fn show_report(&mut self, problems_only: bool) {
+        let mut entries: Vec<Catalog> = match self.signature_handler.load_catalog().await {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to load the catalog: {}", e);
+                self.record(Outcome::Infrastructure);
+                return;
+            }
+        };
+        entries.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+        let total: usize = entries.len();
+        if problems_only {
+            entries.retain(|entry| Outcome::of_status(&entry.last_status) > Outcome::Initialized);
+        }
+
+        println!("{:<20} {:>7} {:<25} {:>12}  file", "status", "version", "last verified", "size");
+        for entry in &entries {
+            println!(
+                "{:<20} {:>7} {:<25} {:>12}  {}",
+                entry.last_status,
+                entry.signature_version.map(|version| version.to_string()).unwrap_or("-".to_string()),
+                entry.last_verified.as_deref().unwrap_or("never"),
+                entry.size.map(|size| size.to_string()).unwrap_or("-".to_string()),
+                self.file_handler.prepare_file_path(&entry.file_name)
+            );
+        }
+        info!("{} of {} tracked files listed", entries.len(), total);
+        for entry in &entries {
+            self.record(Outcome::of_status(&entry.last_status));
         }
     }
 
     fn display_files_status(&self) {
-        for (file, file_status) in &self.files_status {
+        for (file_name, file_status) in &self.files_status {
+            let file: String = self.file_handler.prepare_file_path(file_name);
             match file_status.status.as_str() {
                 "initialized" => warn!("File '{}' saved and signature generated.", file),
                 "valid" => info!("File '{}' integrity valid.", file),
                 "corrupted" => error!("File '{}' integrity check invalid.", file),
                 "error" => error!("File '{}' integrity check error.", file),
+                "tampered-signature" => error!("File '{}' stored signature record failed verification.", file),
+                "missing-signature" => error!("File '{}' stored signature record is missing.", file),
+                "missing" => error!("File '{}' missing from storage.", file),
+                "skipped" => warn!("File '{}' skipped.", file),
+                "repaired" => warn!("File '{}' corrupted chunks repaired.", file),
+                "restored" => warn!("File '{}' restored from the vault.", file),
+                "would-restore" => info!("File '{}' would be restored from the vault.", file),
+                "restore-failed" => error!("File '{}' restore failed.", file),
+                "rotated" => info!("File '{}' vault copy rewrapped under the current key.", file),
+                "key-current" => info!("File '{}' vault copy already under the current key.", file),
+                "rotate-failed" => error!("File '{}' vault copy key rotation failed.", file),
+                "accepted" => warn!("File '{}' change accepted as a new signature version.", file),
+                "accept-failed" => error!("File '{}' change could not be accepted.", file),
+                "scrubbed" => info!("File '{}' vault copy intact.", file),
+                "vault-damaged" => error!("File '{}' vault copy damaged, the next backup replaces it.", file),
+                "vault-key-missing" => error!("File '{}' vault copy is sealed under a key that is not configured.", file),
                 _ => {}
             }
         }
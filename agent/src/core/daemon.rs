@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+use chrono::{DateTime, Local};
+use croner::Cron;
+use log::{error, info, warn};
+use notify::Event;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use crate::config::environment::Environment;
+use crate::core::core::Core;
+use crate::core::outcome::{Outcome, StartupError};
+use crate::core::task::Task;
+use crate::core::watcher::FileWatcher;
+
+/// A signal the daemon acts on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(unix), allow(dead_code))]
+enum Received {
+    Terminate,
+    Interrupt,
+    Hangup,
+}
+
+impl fmt::Display for Received {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name: &str = match self {
+            Received::Terminate => "SIGTERM",
+            Received::Interrupt => "SIGINT",
+            Received::Hangup => "SIGHUP",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// The signals the daemon listens for. Outside Unix only Ctrl-C is
+/// delivered, so the daemon cannot be told to reload there.
+struct Signals {
+    #[cfg(unix)]
+    terminate: Signal,
+    #[cfg(unix)]
+    interrupt: Signal,
+    #[cfg(unix)]
+    hangup: Signal,
+}
+
+impl Signals {
+    #[cfg(unix)]
+    fn new() -> Result<Self, StartupError> {
+        let listen = |kind: SignalKind| signal(kind)
+            .map_err(|e| StartupError::infrastructure(format!("Failed to listen for signals: {}", e)));
+
+        Ok(Self {
+            terminate: listen(SignalKind::terminate())?,
+            interrupt: listen(SignalKind::interrupt())?,
+            hangup: listen(SignalKind::hangup())?,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn new() -> Result<Self, StartupError> {
+        Ok(Self {})
+    }
+
+    #[cfg(unix)]
+    async fn recv(&mut self) -> Received {
+        tokio::select! {
+            _ = self.terminate.recv() => Received::Terminate,
+            _ = self.interrupt.recv() => Received::Interrupt,
+            _ = self.hangup.recv() => Received::Hangup,
+        }
+    }
+
+    #[cfg(not(unix))]
+    async fn recv(&mut self) -> Received {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+
+        Received::Interrupt
+    }
+}
+
+/// A job the daemon starts on a schedule of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Job {
+    Verify,
+    Backup,
+    Scrub,
+}
+
+impl Job {
+    fn task(self, env: &Environment) -> Task {
+        match self {
+            Job::Verify => Task::Verify { patterns: Vec::new(), repair: env.repair_corrupted },
+            Job::Backup => Task::Backup { repair: env.repair_corrupted },
+            Job::Scrub => Task::Scrub,
+        }
+    }
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Job::Verify => write!(f, "verify"),
+            Job::Backup => write!(f, "backup"),
+            Job::Scrub => write!(f, "scrub"),
+        }
+    }
+}
+
+/// A job and the next time its cron expression fires, in local time.
+struct Schedule {
+    job: Job,
+    cron: Cron,
+    next: DateTime<Local>,
+}
+
+impl Schedule {
+    fn new(job: Job, expression: &str, now: &DateTime<Local>) -> Result<Self, String> {
+        let cron: Cron = Cron::new(expression)
+            .with_seconds_optional()
+            .parse()
+            .map_err(|e| format!("Invalid {} schedule '{}': {}", job, expression, e))?;
+        let mut schedule: Schedule = Self { job, cron, next: *now };
+        schedule.advance(now)?;
+
+        Ok(schedule)
+    }
+
+    fn advance(&mut self, after: &DateTime<Local>) -> Result<(), String> {
+        self.next = self.cron
+            .find_next_occurrence(after, false)
+            .map_err(|e| format!("The {} schedule never fires again: {}", self.job, e))?;
+
+        Ok(())
+    }
+
+    /// The configured schedules. The daemon needs at least one unless it
+    /// watches for changes.
+    fn from_environment(env: &Environment) -> Result<Vec<Self>, String> {
+        let now: DateTime<Local> = Local::now();
+        let expressions: [(Job, Option<&String>); 3] = [
+            (Job::Verify, env.schedule_verify.as_ref()),
+            (Job::Backup, env.schedule_backup.as_ref()),
+            (Job::Scrub, env.schedule_scrub.as_ref()),
+        ];
+        let schedules: Vec<Self> = expressions
+            .into_iter()
+            .filter_map(|(job, expression)| expression.map(|expression| Self::new(job, expression, &now)))
+            .collect::<Result<_, _>>()?;
+
+        if schedules.is_empty() && !env.watch {
+            return Err("The daemon needs at least one of SCHEDULE_VERIFY, SCHEDULE_BACKUP, SCHEDULE_SCRUB or WATCH".to_string());
+        }
+
+        Ok(schedules)
+    }
+}
+
+/// Moves `schedules[index]`, whose job started at `started` and finished
+/// at `now`, to its next firing. Firings of the other schedules that came
+/// while it ran are dropped rather than started late, so runs never pile
+/// up behind a slow one.
+fn reschedule(schedules: &mut Vec<Schedule>, index: usize, started: &DateTime<Local>, now: &DateTime<Local>) {
+    let job: Job = schedules[index].job;
+    if let Err(e) = schedules[index].advance(started) {
+        error!("{}", e);
+        schedules.remove(index);
+    }
+    let mut exhausted: Vec<usize> = Vec::new();
+    for (position, schedule) in schedules.iter_mut().enumerate() {
+        if schedule.next <= *started || schedule.next > *now {
+            continue;
+        }
+        warn!("Skipping the {} job due at {}: the {} job was still running", schedule.job, schedule.next.to_rfc3339(), job);
+        if let Err(e) = schedule.advance(now) {
+            error!("{}", e);
+            exhausted.push(position);
+        }
+    }
+    for position in exhausted.into_iter().rev() {
+        schedules.remove(position);
+    }
+}
+
+/// Keeps one `Core`, and with it the signature store connection, open
+/// between runs and starts the scheduled jobs, and the checks of files a
+/// watch saw change, one at a time. SIGTERM and SIGINT stop it once the
+/// running job is done; SIGHUP reloads the configuration, reading
+/// `ENV_FILE` again.
+pub struct Daemon {
+    overrides: HashMap<&'static str, String>,
+    env: Environment,
+    core: Core,
+    schedules: Vec<Schedule>,
+    watcher: Option<FileWatcher>,
+    signals: Signals,
+}
+
+impl Daemon {
+    pub async fn new(overrides: HashMap<&'static str, String>, env: Environment) -> Result<Self, StartupError> {
+        let schedules: Vec<Schedule> = Schedule::from_environment(&env).map_err(StartupError::configuration)?;
+        let signals: Signals = Signals::new()?;
+        let core: Core = Core::new(&env).await?;
+        let watcher: Option<FileWatcher> = match env.watch {
+            true => Some(FileWatcher::new(&env).map_err(StartupError::infrastructure)?),
+            false => None,
+        };
+
+        Ok(Self {
+            overrides,
+            env,
+            core,
+            schedules,
+            watcher,
+            signals,
+        })
+    }
+
+    /// Runs until asked to stop, and returns the outcome the process
+    /// exits with.
+    pub async fn run(mut self) -> Outcome {
+        self.log_schedules();
+
+        loop {
+            let index: Option<usize> = self.schedules
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, schedule)| schedule.next)
+                .map(|(index, _)| index);
+            if index.is_none() && self.watcher.is_none() {
+                error!("No schedule fires any more, stopping");
+                return Outcome::Configuration;
+            }
+            let job_wait: Option<Duration> = index.map(|index| (self.schedules[index].next - Local::now())
+                .to_std()
+                .unwrap_or(Duration::ZERO));
+            let check_wait: Option<Duration> = self.watcher.as_ref().and_then(FileWatcher::next_due);
+            let wait: Option<Duration> = match (job_wait, check_wait) {
+                (Some(job_wait), Some(check_wait)) => Some(job_wait.min(check_wait)),
+                (job_wait, check_wait) => job_wait.or(check_wait),
+            };
+
+            tokio::select! {
+                _ = sleep(wait) => {}
+                Some(event) = next_event(&mut self.watcher) => {
+                    if let Some(watcher) = &mut self.watcher {
+                        watcher.observe(event);
+                    }
+                    continue;
+                }
+                received = self.signals.recv() => match received {
+                    Received::Terminate | Received::Interrupt => {
+                        info!("Received {}, stopping", received);
+                        return Outcome::Valid;
+                    }
+                    Received::Hangup => {
+                        self.reload().await;
+                        continue;
+                    }
+                },
+            }
+
+            let file_names: Vec<String> = self.watcher
+                .as_mut()
+                .map(FileWatcher::take_due)
+                .unwrap_or_default();
+            let (stop, reload): (bool, bool) = if !file_names.is_empty() {
+                let what: String = format!("check of {} changed files", file_names.len());
+                self.run_task(&what, Task::Check { file_names }).await
+            } else {
+                match index {
+                    Some(index) if self.schedules[index].next <= Local::now() => self.run_job(index).await,
+                    _ => continue,
+                }
+            };
+            if stop {
+                info!("Stopping");
+                return Outcome::Valid;
+            }
+            if reload {
+                self.reload().await;
+            }
+        }
+    }
+
+    /// Runs the job of `schedules[index]` and moves its schedule, and any
+    /// that fired meanwhile, to their next firing.
+    async fn run_job(&mut self, index: usize) -> (bool, bool) {
+        let job: Job = self.schedules[index].job;
+        let started: DateTime<Local> = Local::now();
+        let task: Task = job.task(&self.env);
+        let (stop, reload): (bool, bool) = self.run_task(&format!("scheduled {} job", job), task).await;
+        reschedule(&mut self.schedules, index, &started, &Local::now());
+
+        (stop, reload)
+    }
+
+    /// Runs `task` to completion, still listening for signals, and returns
+    /// whether to stop and whether to reload.
+    async fn run_task(&mut self, what: &str, task: Task) -> (bool, bool) {
+        let mut stop: bool = false;
+        let mut reload: bool = false;
+        info!("Starting the {}", what);
+
+        let outcome: Outcome = {
+            let run = self.core.run(task);
+            tokio::pin!(run);
+            loop {
+                tokio::select! {
+                    outcome = &mut run => break outcome,
+                    received = self.signals.recv() => match received {
+                        Received::Terminate | Received::Interrupt => {
+                            warn!("Received {}, stopping once the {} finishes", received, what);
+                            stop = true;
+                        }
+                        Received::Hangup => {
+                            info!("Received {}, reloading once the {} finishes", received, what);
+                            reload = true;
+                        }
+                    },
+                }
+            }
+        };
+        info!("Finished the {}: {:?}, exit code {}", what, outcome, outcome.exit_code());
+
+        (stop, reload)
+    }
+
+    /// Rebuilds the configuration, the core, the schedules and the watch,
+    /// which keeps the changes not checked yet. A new configuration that
+    /// fails to load leaves the current one running.
+    async fn reload(&mut self) {
+        info!("Reloading the configuration");
+        let env: Environment = match Environment::new(&self.overrides) {
+            Ok(env) => env,
+            Err(e) => {
+                error!("Keeping the current configuration, failed to load environment variables: {}", e);
+                return;
+            }
+        };
+        let schedules: Vec<Schedule> = match Schedule::from_environment(&env) {
+            Ok(schedules) => schedules,
+            Err(e) => {
+                error!("Keeping the current configuration: {}", e);
+                return;
+            }
+        };
+        let core: Core = match Core::new(&env).await {
+            Ok(core) => core,
+            Err(e) => {
+                error!("Keeping the current configuration: {}", e);
+                return;
+            }
+        };
+        let mut watcher: Option<FileWatcher> = match env.watch {
+            true => match FileWatcher::new(&env) {
+                Ok(watcher) => Some(watcher),
+                Err(e) => {
+                    error!("Keeping the current configuration: {}", e);
+                    return;
+                }
+            },
+            false => None,
+        };
+        if let (Some(watcher), Some(previous)) = (watcher.as_mut(), self.watcher.take()) {
+            watcher.carry_over(previous);
+        }
+
+        self.env = env;
+        self.core = core;
+        self.schedules = schedules;
+        self.watcher = watcher;
+        self.log_schedules();
+    }
+
+    fn log_schedules(&self) {
+        for schedule in &self.schedules {
+            info!(
+                "Scheduled the {} job on '{}', next at {}",
+                schedule.job,
+                schedule.cron.pattern,
+                schedule.next.to_rfc3339()
+            );
+        }
+    }
+}
+
+/// Sleeps for `wait`, or forever when there is nothing to wait for.
+async fn sleep(wait: Option<Duration>) {
+    match wait {
+        Some(wait) => tokio::time::sleep(wait).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// The next event of the watch, or never when there is no watch.
+async fn next_event(watcher: &mut Option<FileWatcher>) -> Option<notify::Result<Event>> {
+    match watcher {
+        Some(watcher) => watcher.next_event().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 1, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn advances_to_the_next_firing() {
+        let mut schedule: Schedule = Schedule::new(Job::Backup, "0 2 * * *", &at(5, 1, 0)).unwrap();
+        assert_eq!(schedule.next, at(5, 2, 0));
+
+        schedule.advance(&at(5, 2, 0)).unwrap();
+        assert_eq!(schedule.next, at(6, 2, 0));
+
+        let seconds: Schedule = Schedule::new(Job::Verify, "30 */15 * * * *", &at(5, 1, 0)).unwrap();
+        assert_eq!(seconds.next, at(5, 1, 0) + chrono::Duration::seconds(30));
+        assert!(Schedule::new(Job::Scrub, "every night", &at(5, 1, 0)).is_err());
+    }
+
+    #[test]
+    fn skips_firings_that_came_while_a_job_ran() {
+        let started: DateTime<Local> = at(5, 2, 0);
+        let mut schedules: Vec<Schedule> = vec![
+            Schedule::new(Job::Backup, "0 2 * * *", &at(5, 1, 0)).unwrap(),
+            Schedule::new(Job::Verify, "*/15 * * * *", &started).unwrap(),
+            Schedule::new(Job::Scrub, "0 4 * * *", &started).unwrap(),
+        ];
+        assert_eq!(schedules[1].next, at(5, 2, 15));
+
+        reschedule(&mut schedules, 0, &started, &at(5, 3, 10));
+        assert_eq!(schedules[0].next, at(6, 2, 0));
+        assert_eq!(schedules[1].next, at(5, 3, 15));
+        assert_eq!(schedules[2].next, at(5, 4, 0));
+    }
+
+    #[test]
+    fn keeps_firings_due_when_a_job_started() {
+        let started: DateTime<Local> = at(5, 2, 0);
+        let mut schedules: Vec<Schedule> = vec![
+            Schedule::new(Job::Backup, "0 2 * * *", &at(5, 1, 0)).unwrap(),
+            Schedule::new(Job::Verify, "0 2 * * *", &at(5, 1, 0)).unwrap(),
+        ];
+
+        reschedule(&mut schedules, 0, &started, &at(5, 2, 30));
+        assert_eq!(schedules[0].next, at(6, 2, 0));
+        assert_eq!(schedules[1].next, started);
+    }
+}
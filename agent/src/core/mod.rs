@@ -1,3 +1,11 @@
+#[allow(clippy::module_inception)]
 mod core;
+mod daemon;
+mod outcome;
+mod task;
+mod watcher;
 
 pub use core::Core;
+pub use daemon::Daemon;
+pub use outcome::{Outcome, StartupError};
+pub use task::Task;
@@ -0,0 +1,111 @@
+use std::fmt;
+use crate::utils::constants::{
+    EXIT_CONFIGURATION,
+    EXIT_CORRUPTED,
+    EXIT_INFRASTRUCTURE,
+    EXIT_INITIALIZED,
+    EXIT_MISSING,
+    EXIT_VALID
+};
+
+/// How a run ended, ordered from best to worst. A run that finds several
+/// kinds of problems ends with the worst of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    Valid,
+    /// Nothing was wrong, but files without a signature got their first one.
+    Initialized,
+    /// A store, vault or file could not be read or written, so some files
+    /// may not have been checked.
+    Infrastructure,
+    Missing,
+    /// Some content or signature record did not match, even if it was
+    /// repaired since.
+    Corrupted,
+    Configuration,
+}
+
+impl Outcome {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Valid => EXIT_VALID,
+            Outcome::Initialized => EXIT_INITIALIZED,
+            Outcome::Infrastructure => EXIT_INFRASTRUCTURE,
+            Outcome::Missing => EXIT_MISSING,
+            Outcome::Corrupted => EXIT_CORRUPTED,
+            Outcome::Configuration => EXIT_CONFIGURATION,
+        }
+    }
+
+    /// The outcome a file left with `status` in the report stands for.
+    pub fn of_status(status: &str) -> Self {
+        match status {
+            "initialized" => Outcome::Initialized,
+            "corrupted" | "tampered-signature" | "missing-signature" | "repaired" | "vault-damaged" => Outcome::Corrupted,
+            "missing" => Outcome::Missing,
+            "vault-key-missing" => Outcome::Configuration,
+            "error" | "restore-failed" | "rotate-failed" | "accept-failed" => Outcome::Infrastructure,
+            _ => Outcome::Valid,
+        }
+    }
+}
+
+/// Why the agent could not start a run, and the outcome it exits with.
+#[derive(Debug)]
+pub struct StartupError {
+    pub outcome: Outcome,
+    pub message: String,
+}
+
+impl StartupError {
+    pub fn configuration(message: String) -> Self {
+        Self { outcome: Outcome::Configuration, message }
+    }
+
+    pub fn infrastructure(message: String) -> Self {
+        Self { outcome: Outcome::Infrastructure, message }
+    }
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Exit code of a run that left files with `statuses`.
+    fn exit_code(statuses: &[&str]) -> i32 {
+        statuses
+            .iter()
+            .map(|status| Outcome::of_status(status))
+            .fold(Outcome::Valid, Outcome::max)
+            .exit_code()
+    }
+
+    #[test]
+    fn exits_with_the_most_severe_outcome() {
+        assert_eq!(exit_code(&[]), EXIT_VALID);
+        assert_eq!(exit_code(&["valid", "scrubbed", "accepted"]), EXIT_VALID);
+        assert_eq!(exit_code(&["valid", "initialized"]), EXIT_INITIALIZED);
+        assert_eq!(exit_code(&["initialized", "error"]), EXIT_INFRASTRUCTURE);
+        assert_eq!(exit_code(&["restore-failed", "missing", "initialized"]), EXIT_MISSING);
+        assert_eq!(exit_code(&["missing", "repaired", "error"]), EXIT_CORRUPTED);
+        assert_eq!(exit_code(&["tampered-signature", "vault-key-missing"]), EXIT_CONFIGURATION);
+    }
+
+    #[test]
+    fn problems_found_are_never_reported_as_valid() {
+        for status in ["corrupted", "tampered-signature", "missing-signature", "repaired", "vault-damaged"] {
+            assert_eq!(Outcome::of_status(status), Outcome::Corrupted, "{}", status);
+        }
+        for status in ["error", "restore-failed", "rotate-failed", "accept-failed"] {
+            assert_eq!(Outcome::of_status(status), Outcome::Infrastructure, "{}", status);
+        }
+        assert_eq!(StartupError::configuration("bad key".to_string()).outcome.exit_code(), EXIT_CONFIGURATION);
+        assert_eq!(StartupError::infrastructure("no store".to_string()).outcome.exit_code(), EXIT_INFRASTRUCTURE);
+    }
+}
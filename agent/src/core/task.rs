@@ -0,0 +1,106 @@
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use crate::config::cli::Command;
+use crate::config::environment::Environment;
+use crate::storage::backup_handler::RestoreOptions;
+use crate::storage::chunk_store::CollectOptions;
+use crate::storage::signature_handler::AcceptOptions;
+
+/// What one run of the agent does, from a subcommand or, without one,
+/// from the environment variables that selected a mode before.
+pub enum Task {
+    Init,
+    Verify { patterns: Vec<String>, repair: bool },
+    Backup { repair: bool },
+    Scrub,
+    /// Checks files a watch saw change, some of which may be gone.
+    Check { file_names: Vec<String> },
+    Restore { file_names: Vec<String>, options: RestoreOptions },
+    Accept { patterns: Vec<String>, options: AcceptOptions },
+    Forget { file_names: Vec<String> },
+    Status { file_name: String },
+    Report { problems_only: bool },
+    RotateKey,
+    Collect(CollectOptions),
+}
+
+impl Task {
+    pub fn new(command: Option<Command>, env: &Environment) -> Result<Self, String> {
+        let command: Command = match command {
+            Some(command) => command,
+            None => return Self::from_environment(env),
+        };
+
+        Ok(match command {
+            Command::Init => Task::Init,
+            Command::Verify { paths, repair } => Task::Verify { patterns: paths, repair },
+            Command::Backup { repair } => Task::Backup { repair },
+            Command::Scrub => Task::Scrub,
+            Command::Restore { paths, to, at, dry_run } => Task::Restore {
+                file_names: paths,
+                options: RestoreOptions {
+                    target_directory: to,
+                    at: parse_time("--at", at.as_deref())?,
+                    dry_run
+                }
+            },
+            Command::Accept { paths, reason, author, override_tampered } => {
+                let author: String = author
+                    .or_else(|| env.accept_author.clone())
+                    .ok_or("--author is required to accept changes when USER is not set")?;
+                Task::Accept { patterns: paths, options: AcceptOptions { author, reason, override_tampered } }
+            }
+            Command::Forget { paths } => Task::Forget { file_names: paths },
+            Command::Status { file } => Task::Status { file_name: file },
+            Command::Report { problems } => Task::Report { problems_only: problems },
+            Command::Daemon { .. } | Command::Watch { .. } => {
+                return Err("The daemon runs its jobs as tasks of their own".to_string());
+            }
+        })
+    }
+
+    /// Picks the mode the environment asks for: a restore, a key rotation,
+    /// a chunk collection, an accept, or else a verification and backup.
+    fn from_environment(env: &Environment) -> Result<Self, String> {
+        if let Some(file_names) = &env.restore_files {
+            return Ok(Task::Restore {
+                file_names: file_names.clone(),
+                options: RestoreOptions {
+                    target_directory: env.restore_directory.clone(),
+                    at: parse_time("RESTORE_AT", env.restore_at.as_deref())?,
+                    dry_run: env.restore_dry_run
+                }
+            });
+        }
+        if env.rotate_key {
+            return Ok(Task::RotateKey);
+        }
+        if env.collect_garbage {
+            return Ok(Task::Collect(CollectOptions {
+                grace_period: Duration::from_secs(env.collect_grace_hours * 3600),
+                dry_run: env.collect_dry_run
+            }));
+        }
+        if let Some(patterns) = &env.accept_files {
+            let reason: String = env.accept_reason
+                .clone()
+                .ok_or("ACCEPT_REASON is required to accept changes")?;
+            let author: String = env.accept_author
+                .clone()
+                .ok_or("ACCEPT_AUTHOR is required to accept changes")?;
+            return Ok(Task::Accept {
+                patterns: patterns.clone(),
+                options: AcceptOptions { author, reason, override_tampered: env.accept_override_tampered }
+            });
+        }
+
+        Ok(Task::Backup { repair: env.repair_corrupted })
+    }
+}
+
+fn parse_time(name: &str, value: Option<&str>) -> Result<Option<DateTime<Utc>>, String> {
+    value
+        .map(|at| DateTime::parse_from_rfc3339(at).map(|at| at.with_timezone(&Utc)))
+        .transpose()
+        .map_err(|e| format!("{} is not an RFC 3339 timestamp: {}", name, e))
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use glob::Pattern;
+use log::{error, info, warn};
+use notify::event::{AccessKind, AccessMode, CreateKind, ModifyKind, RenameMode};
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use crate::config::environment::Environment;
+use crate::storage::file_handler::{FileHandler, PATTERN_OPTIONS};
+
+/// A file that changed and has not been checked yet.
+struct Change {
+    /// A writer modified the file and has not closed it since.
+    writing: bool,
+    last_event: Instant,
+}
+
+/// Subscribes to inotify events under the storage directory and tells when
+/// each changed file has settled enough to be checked. A file closed after
+/// writing, moved into place or removed is checked once no event came for
+/// the debounce time; a file still open for writing is left alone until
+/// it is closed, or until it stays quiet for the settle time.
+pub struct FileWatcher {
+    // Dropping the watcher ends the subscription.
+    _watcher: RecommendedWatcher,
+    events: UnboundedReceiver<notify::Result<Event>>,
+    file_handler: FileHandler,
+    immutable: Vec<Pattern>,
+    debounce: Duration,
+    settle: Duration,
+    pending: HashMap<String, Change>,
+}
+
+impl FileWatcher {
+    pub fn new(env: &Environment) -> Result<Self, String> {
+        let file_handler: FileHandler = FileHandler::new(&env.storage_directory);
+        let immutable: Vec<Pattern> = file_handler.compile_patterns(&env.watch_immutable)?;
+        let (sender, events): (UnboundedSender<notify::Result<Event>>, UnboundedReceiver<notify::Result<Event>>) = unbounded_channel();
+        let mut watcher: RecommendedWatcher = RecommendedWatcher::new(
+            move |event: notify::Result<Event>| {
+                let _ = sender.send(event);
+            },
+            Config::default()
+        ).map_err(|e| format!("Failed to start watching: {}", e))?;
+        watcher.watch(Path::new(&env.storage_directory), RecursiveMode::Recursive)
+            .map_err(|e| format!("Failed to watch {}: {}", env.storage_directory, e))?;
+        info!("Watching {} for changes", env.storage_directory);
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            file_handler,
+            immutable,
+            debounce: Duration::from_millis(env.watch_debounce_ms),
+            settle: Duration::from_secs(env.watch_settle_seconds),
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Keeps the changes `previous` had not checked yet, across a reload,
+    /// along with the events it had received but not observed.
+    pub fn carry_over(&mut self, mut previous: FileWatcher) {
+        self.pending.extend(previous.pending.drain());
+        while let Ok(event) = previous.events.try_recv() {
+            self.observe(event);
+        }
+    }
+
+    pub async fn next_event(&mut self) -> Option<notify::Result<Event>> {
+        self.events.recv().await
+    }
+
+    pub fn observe(&mut self, event: notify::Result<Event>) {
+        let event: Event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                error!("File watch error: {}", e);
+                return;
+            }
+        };
+        if event.need_rescan() {
+            warn!("File events were dropped, run verify to catch up on changes the watch missed");
+        }
+        // Whether a writer still holds the file open after this event, or
+        // None for events that say nothing about its content.
+        let writing: Option<bool> = match event.kind {
+            EventKind::Create(CreateKind::Folder) => None,
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_)) => Some(true),
+            EventKind::Access(AccessKind::Close(AccessMode::Write)) => Some(false),
+            EventKind::Modify(ModifyKind::Name(RenameMode::From | RenameMode::To | RenameMode::Both | RenameMode::Any)) => Some(false),
+            EventKind::Remove(_) => Some(false),
+            _ => None,
+        };
+        let writing: bool = match writing {
+            Some(writing) => writing,
+            None => return,
+        };
+
+        for path in &event.paths {
+            let file_name: String = match self.file_handler.relative_name(path) {
+                Some(file_name) => file_name,
+                None => continue,
+            };
+            let file_path: String = self.file_handler.prepare_file_path(&file_name);
+            if self.needs_alert(&file_name) {
+                error!("ALERT: immutable file '{}' is being changed ({:?})", file_path, event.kind);
+            }
+            let previous: Option<Change> = self.pending.insert(file_name.clone(), Change {
+                writing,
+                last_event: Instant::now(),
+            });
+
+            match previous {
+                Some(previous) if previous.writing && !writing => info!("File '{}' closed after writing", file_path),
+                Some(previous) if !previous.writing && writing => info!("File '{}' is being written again", file_path),
+                None if writing => info!("File '{}' is being written", file_path),
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether an event on `file_name` is alerted on: the first one on an
+    /// immutable file since it was last checked.
+    fn needs_alert(&self, file_name: &str) -> bool {
+        !self.pending.contains_key(file_name) && self.is_immutable(file_name)
+    }
+
+    fn is_immutable(&self, file_name: &str) -> bool {
+        self.immutable.iter().any(|pattern| pattern.matches_with(file_name, PATTERN_OPTIONS))
+    }
+
+    /// How long until the next pending change is due, if there is one.
+    pub fn next_due(&self) -> Option<Duration> {
+        let now: Instant = Instant::now();
+
+        self.pending
+            .values()
+            .map(|change| (change.last_event + self.quiet_time(change)).saturating_duration_since(now))
+            .min()
+    }
+
+    /// Takes the changes that are due, sorted by name.
+    pub fn take_due(&mut self) -> Vec<String> {
+        let now: Instant = Instant::now();
+        let mut due: Vec<String> = Vec::new();
+
+        for (file_name, change) in &self.pending {
+            if now.duration_since(change.last_event) < self.quiet_time(change) {
+                continue;
+            }
+            if change.writing {
+                warn!(
+                    "File '{}' was not closed after {} seconds of quiet, checking it anyway",
+                    self.file_handler.prepare_file_path(file_name),
+                    self.settle.as_secs()
+                );
+            }
+            due.push(file_name.clone());
+        }
+        for file_name in &due {
+            self.pending.remove(file_name);
+        }
+        due.sort();
+
+        due
+    }
+
+    fn quiet_time(&self, change: &Change) -> Duration {
+        if change.writing { self.settle } else { self.debounce }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use notify::event::{DataChange, RemoveKind};
+
+    /// Storage directory watched by one test, removed once it is over.
+    struct TestStorage(PathBuf);
+
+    impl TestStorage {
+        fn new(name: &str) -> Self {
+            let path: PathBuf = std::env::temp_dir().join(format!("glacier-watcher-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+
+            Self(path)
+        }
+
+        fn watcher(&self, debounce_ms: u64, settle_seconds: u64, immutable: &str) -> FileWatcher {
+            let overrides: HashMap<&'static str, String> = HashMap::from([
+                ("STORAGE_DIRECTORY", self.0.to_string_lossy().to_string()),
+                ("ENCRYPTION_KEY", "passphrase:watcher test passphrase".to_string()),
+                ("SIGNATURE_STORE", "file".to_string()),
+                ("WATCH_DEBOUNCE_MS", debounce_ms.to_string()),
+                ("WATCH_SETTLE_SECONDS", settle_seconds.to_string()),
+                ("WATCH_IMMUTABLE", immutable.to_string()),
+            ]);
+
+            FileWatcher::new(&Environment::new(&overrides).unwrap()).unwrap()
+        }
+
+        fn event(&self, kind: EventKind, file_name: &str) -> notify::Result<Event> {
+            Ok(Event::new(kind).add_path(self.0.join(file_name)))
+        }
+    }
+
+    impl Drop for TestStorage {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const WRITTEN: EventKind = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+    const CLOSED: EventKind = EventKind::Access(AccessKind::Close(AccessMode::Write));
+
+    #[test]
+    fn checks_a_file_once_it_stays_quiet() {
+        let storage: TestStorage = TestStorage::new("debounce");
+        let mut watcher: FileWatcher = storage.watcher(100, 60, "");
+
+        watcher.observe(storage.event(CLOSED, "a.bin"));
+        watcher.observe(storage.event(EventKind::Remove(RemoveKind::File), "b.bin"));
+        assert!(watcher.take_due().is_empty());
+        assert!(watcher.next_due().is_some_and(|due| due <= Duration::from_millis(100)));
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(watcher.take_due(), vec!["a.bin".to_string(), "b.bin".to_string()]);
+        assert!(watcher.next_due().is_none());
+    }
+
+    #[test]
+    fn waits_for_a_writer_to_close_the_file() {
+        let storage: TestStorage = TestStorage::new("writing");
+        let mut watcher: FileWatcher = storage.watcher(0, 60, "");
+
+        watcher.observe(storage.event(EventKind::Create(CreateKind::File), "a.bin"));
+        watcher.observe(storage.event(WRITTEN, "a.bin"));
+        assert!(watcher.take_due().is_empty());
+        assert!(watcher.next_due().is_some_and(|due| due > Duration::from_secs(30)));
+
+        watcher.observe(storage.event(CLOSED, "a.bin"));
+        assert_eq!(watcher.take_due(), vec!["a.bin".to_string()]);
+
+        let mut settled: FileWatcher = storage.watcher(0, 0, "");
+        settled.observe(storage.event(WRITTEN, "b.bin"));
+        assert_eq!(settled.take_due(), vec!["b.bin".to_string()]);
+    }
+
+    #[test]
+    fn alerts_on_the_first_change_of_an_immutable_file() {
+        let storage: TestStorage = TestStorage::new("immutable");
+        let mut watcher: FileWatcher = storage.watcher(0, 60, "keys/*,*.iso");
+
+        assert!(watcher.needs_alert("keys/id.pem"));
+        assert!(watcher.needs_alert("disk.iso"));
+        assert!(!watcher.needs_alert("photos/a.jpg"));
+
+        watcher.observe(storage.event(WRITTEN, "keys/id.pem"));
+        assert!(!watcher.needs_alert("keys/id.pem"));
+
+        watcher.observe(storage.event(CLOSED, "keys/id.pem"));
+        assert_eq!(watcher.take_due(), vec!["keys/id.pem".to_string()]);
+        assert!(watcher.needs_alert("keys/id.pem"));
+    }
+}
@@ -4,14 +4,59 @@ mod security;
 mod storage;
 mod utils;
 
-use log::info;
+use clap::Parser;
+use std::collections::HashMap;
+use log::{error, info};
+
+use config::cli::Cli;
+use config::environment::Environment;
+use core::{Daemon, Outcome, StartupError, Task};
+use utils::constants::{EXIT_CONFIGURATION, EXIT_INFRASTRUCTURE, EXIT_VALID};
 
 #[tokio::main]
 async fn main() {
-    config::logger::Logger::init().expect("Failed to initialize logger");
+    let cli: Cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            // Help and version requests are not errors.
+            let _ = e.print();
+            std::process::exit(if e.use_stderr() { EXIT_CONFIGURATION } else { EXIT_VALID });
+        }
+    };
+    if let Err(e) = config::logger::Logger::init() {
+        eprintln!("Failed to initialize logger: {}", e);
+        std::process::exit(EXIT_INFRASTRUCTURE);
+    }
  
     info!("Starting Glacier application");
-    let mut core = core::Core::new().await;
-    core.run().await;
-    info!("Glacier application completed");
+    let overrides: HashMap<&'static str, String> = cli.overrides();
+    let env: Environment = match Environment::new(&overrides) {
+        Ok(env) => env,
+        Err(e) => exit_with(StartupError::configuration(format!("Failed to load environment variables: {}", e))),
+    };
+    if cli.is_daemon() {
+        let daemon: Daemon = match Daemon::new(overrides, env).await {
+            Ok(daemon) => daemon,
+            Err(e) => exit_with(e),
+        };
+        let outcome: Outcome = daemon.run().await;
+        info!("Glacier daemon stopped");
+        std::process::exit(outcome.exit_code());
+    }
+    let task: Task = match Task::new(cli.command, &env) {
+        Ok(task) => task,
+        Err(e) => exit_with(StartupError::configuration(e)),
+    };
+    let mut core = match core::Core::new(&env).await {
+        Ok(core) => core,
+        Err(e) => exit_with(e),
+    };
+    let outcome: Outcome = core.run(task).await;
+    info!("Glacier application completed: {:?}, exit code {}", outcome, outcome.exit_code());
+    std::process::exit(outcome.exit_code());
+}
+
+fn exit_with(e: StartupError) -> ! {
+    error!("{}", e);
+    std::process::exit(e.outcome.exit_code());
 }
@@ -0,0 +1,181 @@
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use crate::utils::constants::{
+    ENCRYPTION_KEY_SIZE,
+    ENCRYPTION_KEY_HEX_PREFIX,
+    ENCRYPTION_KEY_BASE64_PREFIX,
+    ENCRYPTION_KEY_PASSPHRASE_PREFIX,
+    ENCRYPTION_PASSPHRASE_MIN_LENGTH,
+    KDF_ALGORITHM,
+    KDF_MEMORY_KIB,
+    KDF_ITERATIONS,
+    KDF_PARALLELISM,
+    KDF_SALT_SIZE,
+    KDF_KEY_CHECK_DOMAIN
+};
+
+/// Argon2id parameters and salt a passphrase was first derived with. The
+/// parameters file holds one record per passphrase in use, and losing it
+/// makes every copy encrypted under those passphrases unreadable.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct KdfParameters {
+    pub algorithm: String,
+    pub version: u32,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub salt: String,
+    /// Hash of the derived key, so a mistyped passphrase is reported at
+    /// startup rather than as undecryptable vault copies.
+    pub key_check: String,
+}
+
+/// Turns `ENCRYPTION_KEY` into an AES-256 key. `hex:` and `base64:` carry a
+/// raw 32-byte key, `passphrase:` forces key derivation, and an unprefixed
+/// value is a passphrase. An unprefixed value of exactly 32 bytes, which is
+/// how raw keys were given before derivation was supported, is refused
+/// rather than guessed at. A passphrase that is
+/// not recorded yet is only accepted with `allow_new`, or for the first one.
+pub fn resolve_key(encryption_key: &str, kdf_path: &str, allow_new: bool) -> Result<[u8; ENCRYPTION_KEY_SIZE], String> {
+    if let Some(encoded) = encryption_key.strip_prefix(ENCRYPTION_KEY_HEX_PREFIX) {
+        let bytes: Vec<u8> = hex::decode(encoded.trim())
+            .map_err(|e| format!("ENCRYPTION_KEY is not valid hex: {}", e))?;
+        return raw_key(bytes);
+    }
+    if let Some(encoded) = encryption_key.strip_prefix(ENCRYPTION_KEY_BASE64_PREFIX) {
+        let bytes: Vec<u8> = BASE64.decode(encoded.trim())
+            .map_err(|e| format!("ENCRYPTION_KEY is not valid base64: {}", e))?;
+        return raw_key(bytes);
+    }
+    if let Some(passphrase) = encryption_key.strip_prefix(ENCRYPTION_KEY_PASSPHRASE_PREFIX) {
+        return derive_key(passphrase, kdf_path, allow_new);
+    }
+    if encryption_key.len() == ENCRYPTION_KEY_SIZE {
+        return Err(format!(
+            "an unprefixed ENCRYPTION_KEY of {} characters is ambiguous, prefix it with {} to derive a key from it, or give the raw key as {} or {}",
+            ENCRYPTION_KEY_SIZE,
+            ENCRYPTION_KEY_PASSPHRASE_PREFIX,
+            ENCRYPTION_KEY_HEX_PREFIX,
+            ENCRYPTION_KEY_BASE64_PREFIX
+        ));
+    }
+
+    derive_key(encryption_key, kdf_path, allow_new)
+}
+
+fn raw_key(bytes: Vec<u8>) -> Result<[u8; ENCRYPTION_KEY_SIZE], String> {
+    let length: usize = bytes.len();
+
+    bytes.try_into()
+        .map_err(|_| format!("ENCRYPTION_KEY must decode to {} bytes, got {}", ENCRYPTION_KEY_SIZE, length))
+}
+
+fn derive_key(passphrase: &str, kdf_path: &str, allow_new: bool) -> Result<[u8; ENCRYPTION_KEY_SIZE], String> {
+    if passphrase.chars().count() < ENCRYPTION_PASSPHRASE_MIN_LENGTH {
+        return Err(format!(
+            "ENCRYPTION_KEY passphrase must be at least {} characters long",
+            ENCRYPTION_PASSPHRASE_MIN_LENGTH
+        ));
+    }
+
+    let mut records: Vec<KdfParameters> = load_parameters(kdf_path)?;
+    for parameters in &records {
+        let key: [u8; ENCRYPTION_KEY_SIZE] = run_argon2(passphrase, parameters)?;
+        if key_check(&key) == parameters.key_check {
+            info!("Derived encryption key with the parameters in {}", kdf_path);
+            return Ok(key);
+        }
+    }
+    if !records.is_empty() && !allow_new {
+        return Err(format!(
+            "passphrase does not match any passphrase recorded in {}, set ROTATE_KEY to introduce a new one",
+            kdf_path
+        ));
+    }
+
+    let mut salt: [u8; KDF_SALT_SIZE] = [0u8; KDF_SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+    let mut parameters: KdfParameters = KdfParameters {
+        algorithm: KDF_ALGORITHM.to_string(),
+        version: Version::V0x13.into(),
+        memory_kib: KDF_MEMORY_KIB,
+        iterations: KDF_ITERATIONS,
+        parallelism: KDF_PARALLELISM,
+        salt: hex::encode(salt),
+        key_check: String::new(),
+    };
+    let key: [u8; ENCRYPTION_KEY_SIZE] = run_argon2(passphrase, &parameters)?;
+    parameters.key_check = key_check(&key);
+    records.push(parameters);
+    write_parameters(kdf_path, &records)?;
+    warn!("Stored new key derivation parameters at {}", kdf_path);
+
+    Ok(key)
+}
+
+fn run_argon2(passphrase: &str, parameters: &KdfParameters) -> Result<[u8; ENCRYPTION_KEY_SIZE], String> {
+    if parameters.algorithm != KDF_ALGORITHM {
+        return Err(format!("Unsupported key derivation algorithm: {}", parameters.algorithm));
+    }
+    let version: Version = Version::try_from(parameters.version)
+        .map_err(|e| format!("Unsupported Argon2 version {}: {}", parameters.version, e))?;
+    let salt: Vec<u8> = hex::decode(&parameters.salt)
+        .map_err(|e| format!("Invalid key derivation salt: {}", e))?;
+    let params: Params = Params::new(
+        parameters.memory_kib,
+        parameters.iterations,
+        parameters.parallelism,
+        Some(ENCRYPTION_KEY_SIZE)
+    ).map_err(|e| format!("Invalid key derivation parameters: {}", e))?;
+
+    let mut key: [u8; ENCRYPTION_KEY_SIZE] = [0u8; ENCRYPTION_KEY_SIZE];
+    Argon2::new(Algorithm::Argon2id, version, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| format!("Failed to derive encryption key: {}", e))?;
+
+    Ok(key)
+}
+
+fn key_check(key: &[u8; ENCRYPTION_KEY_SIZE]) -> String {
+    let mut hasher: Sha256 = Sha256::new();
+    hasher.update(KDF_KEY_CHECK_DOMAIN);
+    hasher.update(key);
+
+    hex::encode(hasher.finalize())
+}
+
+/// Reads the recorded parameters, also accepting a file holding a single
+/// record as written before several passphrases could be recorded.
+fn load_parameters(kdf_path: &str) -> Result<Vec<KdfParameters>, String> {
+    let contents: Vec<u8> = match fs::read(kdf_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read key derivation parameters {}: {}", kdf_path, e)),
+    };
+
+    serde_json::from_slice::<Vec<KdfParameters>>(&contents)
+        .or_else(|_| serde_json::from_slice::<KdfParameters>(&contents).map(|parameters| vec![parameters]))
+        .map_err(|e| format!("Failed to parse key derivation parameters {}: {}", kdf_path, e))
+}
+
+fn write_parameters(kdf_path: &str, records: &[KdfParameters]) -> Result<(), String> {
+    if let Some(parent) = Path::new(kdf_path).parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create key directory: {}", e))?;
+    }
+    let contents: Vec<u8> = serde_json::to_vec_pretty(records)
+        .map_err(|e| format!("Failed to serialize key derivation parameters: {}", e))?;
+    let temporary_path: String = format!("{}.tmp", kdf_path);
+
+    File::create(&temporary_path)
+        .and_then(|mut file| file.write_all(&contents).and_then(|_| file.sync_all()))
+        .and_then(|_| fs::rename(&temporary_path, kdf_path))
+        .map_err(|e| format!("Failed to write key derivation parameters {}: {}", kdf_path, e))
+}
@@ -1 +1,5 @@
-pub mod security;
\ No newline at end of file
+pub mod key_derivation;
+#[allow(clippy::module_inception)]
+pub mod security;
+pub mod signing_handler;
+pub mod stream;
@@ -6,45 +6,194 @@ use aes_gcm::{
 use aes_gcm::aead::generic_array::GenericArray;
 use aes_gcm::aead::consts::U12;
 
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::security::key_derivation::resolve_key;
+use crate::security::stream::{StreamDecryptor, StreamEncryptor};
+use crate::utils::constants::{ENCRYPTION_KEY_ID_DOMAIN, ENCRYPTION_KEY_SIZE, STREAM_SEGMENT_SIZE, STREAM_TAG_SIZE};
+
+const NONCE_SIZE: usize = 12;
+
+#[derive(Debug)]
+pub enum SecurityError {
+    /// Input is shorter than the nonce, tag or header it has to carry.
+    TooShort { length: usize, minimum: usize },
+    /// Ciphertext, nonce or associated data do not authenticate under the key.
+    AuthenticationFailed,
+    /// Input does not start with a known format marker.
+    UnknownFormat,
+    UnsupportedVersion(u8),
+    /// Data is sealed under a key that is not configured.
+    WrongKey(String),
+    /// An encrypted stream ends before its last segment.
+    Truncated,
+    /// An encrypted stream goes on past its last segment.
+    TrailingData,
+    EncryptionFailed,
+    Io(io::Error),
+}
+
+impl fmt::Display for SecurityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecurityError::TooShort { length, minimum } => {
+                write!(f, "encrypted input is {} bytes, shorter than the {} bytes it needs", length, minimum)
+            }
+            SecurityError::AuthenticationFailed => write!(f, "encrypted input failed authentication"),
+            SecurityError::UnknownFormat => write!(f, "encrypted input has an unknown format"),
+            SecurityError::UnsupportedVersion(version) => write!(f, "unsupported encryption format version {}", version),
+            SecurityError::WrongKey(key_id) => write!(f, "encrypted under key {}, which is not configured", key_id),
+            SecurityError::Truncated => write!(f, "encrypted stream is truncated"),
+            SecurityError::TrailingData => write!(f, "encrypted stream has data past its last segment"),
+            SecurityError::EncryptionFailed => write!(f, "failed to encrypt"),
+            SecurityError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SecurityError {}
+
+/// Streams report failures through `io::Error`; this recovers the
+/// `SecurityError` they carry, if any.
+impl From<io::Error> for SecurityError {
+    fn from(e: io::Error) -> Self {
+        if e.get_ref().is_none() {
+            return SecurityError::Io(e);
+        }
+        let kind: io::ErrorKind = e.kind();
+        match e.into_inner().map(|inner| inner.downcast::<SecurityError>()) {
+            Some(Ok(error)) => *error,
+            Some(Err(inner)) => SecurityError::Io(io::Error::new(kind, inner)),
+            None => SecurityError::Io(io::Error::from(kind)),
+        }
+    }
+}
+
+impl From<SecurityError> for io::Error {
+    fn from(e: SecurityError) -> Self {
+        match e {
+            SecurityError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// AES-256-GCM cipher over one key. The handler built from the configured
+/// secret is the key-encryption key: it only wraps the random data key of
+/// each vault copy, and can carry the previous key during a rotation.
+#[derive(Clone)]
 pub struct SecurityHandler {
     encryption_key: Key<Aes256Gcm>,
+    key_id: String,
+    previous: Option<Box<SecurityHandler>>,
 }
 
 impl SecurityHandler {
-    pub fn new(encryption_key: &str) -> Self {
+    pub fn new(encryption_key: &str, kdf_path: &str, allow_new_passphrase: bool) -> Result<Self, String> {
         info!("Initializing security handler");
 
-        let key: &Key<Aes256Gcm> = Key::<Aes256Gcm>::from_slice(encryption_key.as_bytes());
+        let key_bytes: [u8; ENCRYPTION_KEY_SIZE] = resolve_key(encryption_key, kdf_path, allow_new_passphrase)?;
+        let handler: SecurityHandler = Self::from_key(&key_bytes);
+        info!("Encryption key {} loaded", handler.key_id);
+
+        Ok(handler)
+    }
+
+    pub fn from_key(key_bytes: &[u8; ENCRYPTION_KEY_SIZE]) -> Self {
+        let key: &Key<Aes256Gcm> = Key::<Aes256Gcm>::from_slice(key_bytes);
+        let mut hasher: Sha256 = Sha256::new();
+        hasher.update(ENCRYPTION_KEY_ID_DOMAIN);
+        hasher.update(key_bytes);
 
         Self {
             encryption_key: *key,
+            key_id: hex::encode(&hasher.finalize()[..8]),
+            previous: None,
+        }
+    }
+
+    /// Keeps `previous` available to unwrap data keys that were wrapped
+    /// before the configured key was rotated.
+    pub fn with_previous_key(mut self, previous: SecurityHandler) -> Self {
+        info!("Previous encryption key {} loaded", previous.key_id);
+        self.previous = Some(Box::new(previous));
+        self
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Every key this handler can decrypt with, the configured one first.
+    pub fn keys(&self) -> Vec<&SecurityHandler> {
+        let mut keys: Vec<&SecurityHandler> = vec![self];
+        let mut current: &SecurityHandler = self;
+        while let Some(previous) = current.previous.as_deref() {
+            keys.push(previous);
+            current = previous;
         }
+
+        keys
     }
 
-    pub fn encrypt(&self, content: &[u8]) -> Vec<u8> {
-        info!("Encrypting content");
+    pub fn generate_data_key() -> [u8; ENCRYPTION_KEY_SIZE] {
+        Aes256Gcm::generate_key(&mut OsRng).into()
+    }
+
+    pub fn wrap_key(&self, data_key: &[u8; ENCRYPTION_KEY_SIZE]) -> Result<String, SecurityError> {
+        self.encrypt(data_key).map(hex::encode)
+    }
+
+    /// Unwraps a data key with whichever known key has the id it was
+    /// wrapped under.
+    pub fn unwrap_key(&self, key_id: &str, wrapped_key: &str) -> Result<[u8; ENCRYPTION_KEY_SIZE], SecurityError> {
+        let key: &SecurityHandler = self.keys()
+            .into_iter()
+            .find(|key| key.key_id == key_id)
+            .ok_or_else(|| SecurityError::WrongKey(key_id.to_string()))?;
+        let wrapped: Vec<u8> = hex::decode(wrapped_key)
+            .map_err(|_| SecurityError::UnknownFormat)?;
+
+        key.decrypt(&wrapped)?
+            .try_into()
+            .map_err(|_| SecurityError::UnknownFormat)
+    }
 
+    pub fn encrypt(&self, content: &[u8]) -> Result<Vec<u8>, SecurityError> {
         let nonce: aes_gcm::aead::generic_array::GenericArray<u8, aes_gcm::aead::consts::U12> = Aes256Gcm::generate_nonce(&mut OsRng);
         let cipher: Aes256Gcm = Aes256Gcm::new(&self.encryption_key);
         let ciphered_data: Vec<u8> = cipher
             .encrypt(&nonce, content)
-            .expect("failed to encrypt");
+            .map_err(|_| SecurityError::EncryptionFailed)?;
 
         let mut encrypted_data: Vec<u8> = nonce.to_vec();
         encrypted_data.extend_from_slice(&ciphered_data);
 
-        encrypted_data
+        Ok(encrypted_data)
     }
 
-    pub fn decrypt(&self, content: Vec<u8>) -> Vec<u8> {
-        let (nonce_arr, ciphered_data): (&[u8], &[u8]) = content.split_at(12);
+    pub fn decrypt(&self, content: &[u8]) -> Result<Vec<u8>, SecurityError> {
+        if content.len() < NONCE_SIZE + STREAM_TAG_SIZE {
+            return Err(SecurityError::TooShort { length: content.len(), minimum: NONCE_SIZE + STREAM_TAG_SIZE });
+        }
+        let (nonce_arr, ciphered_data): (&[u8], &[u8]) = content.split_at(NONCE_SIZE);
         let nonce: &GenericArray<u8, U12> = Nonce::from_slice(nonce_arr);
         let cipher: Aes256Gcm = Aes256Gcm::new(&self.encryption_key);
 
-        let plaintext: Vec<u8> = cipher
+        cipher
             .decrypt(nonce, ciphered_data)
-            .expect("failed to decrypt data");
+            .map_err(|_| SecurityError::AuthenticationFailed)
+    }
+
+    /// Encrypts everything written to the returned writer as a segmented
+    /// stream, in memory bounded by one segment whatever the content size.
+    pub fn stream_encryptor<W: Write>(&self, writer: W) -> Result<StreamEncryptor<W>, SecurityError> {
+        StreamEncryptor::new(&self.encryption_key, writer, STREAM_SEGMENT_SIZE)
+    }
 
-        plaintext
+    pub fn stream_decryptor<R: BufRead>(&self, reader: R) -> Result<StreamDecryptor<R>, SecurityError> {
+        StreamDecryptor::new(&self.encryption_key, reader)
     }
 }
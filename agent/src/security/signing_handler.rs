@@ -0,0 +1,88 @@
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use ed25519_dalek::{Signature as Ed25519Signature, Signer, SigningKey, VerifyingKey};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// Holds the agent's Ed25519 key, used to sign every stored signature record
+/// so that write access to the signature store alone cannot bless a file.
+pub struct SigningHandler {
+    signing_key: SigningKey,
+    verifying_key: VerifyingKey,
+    key_id: String,
+}
+
+impl SigningHandler {
+    /// Loads the hex-encoded key seed at `key_path`, creating a new key
+    /// readable only by the agent when none exists yet.
+    pub fn load_or_create(key_path: &str) -> Result<Self, String> {
+        let seed: [u8; 32] = match fs::read_to_string(key_path) {
+            Ok(contents) => {
+                let bytes: Vec<u8> = hex::decode(contents.trim())
+                    .map_err(|e| format!("Invalid signing key in {}: {}", key_path, e))?;
+                bytes.try_into()
+                    .map_err(|_| format!("Signing key in {} must be 32 bytes", key_path))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let mut seed: [u8; 32] = [0u8; 32];
+                OsRng.fill_bytes(&mut seed);
+                Self::write_key(key_path, &seed)?;
+                warn!("Generated a new signing key at {}", key_path);
+                seed
+            }
+            Err(e) => return Err(format!("Failed to read signing key {}: {}", key_path, e)),
+        };
+
+        let signing_key: SigningKey = SigningKey::from_bytes(&seed);
+        let verifying_key: VerifyingKey = signing_key.verifying_key();
+        let key_id: String = hex::encode(&Sha256::digest(verifying_key.as_bytes())[..8]);
+        info!("Loaded signing key {}", key_id);
+
+        Ok(Self {
+            signing_key,
+            verifying_key,
+            key_id,
+        })
+    }
+
+    fn write_key(key_path: &str, seed: &[u8; 32]) -> Result<(), String> {
+        if let Some(parent) = Path::new(key_path).parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create key directory: {}", e))?;
+        }
+        let mut options: OpenOptions = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        options.open(key_path)
+            .and_then(|mut file| file.write_all(hex::encode(seed).as_bytes()).and_then(|_| file.sync_all()))
+            .map_err(|e| format!("Failed to write signing key {}: {}", key_path, e))
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    pub fn sign(&self, message: &[u8]) -> String {
+        hex::encode(self.signing_key.sign(message).to_bytes())
+    }
+
+    pub fn verify(&self, message: &[u8], key_id: &str, signature_hex: &str) -> bool {
+        if key_id != self.key_id {
+            return false;
+        }
+        let bytes: [u8; 64] = match hex::decode(signature_hex).ok().and_then(|bytes| bytes.try_into().ok()) {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+
+        self.verifying_key
+            .verify_strict(message, &Ed25519Signature::from_bytes(&bytes))
+            .is_ok()
+    }
+}
@@ -0,0 +1,314 @@
+use aes_gcm::aead::{Aead, KeyInit, OsRng, Payload};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use std::io::{self, BufRead, Read, Write};
+
+use crate::security::security::SecurityError;
+use crate::utils::constants::{
+    STREAM_HEADER_SIZE,
+    STREAM_MAGIC,
+    STREAM_MAX_SEGMENT_SIZE,
+    STREAM_NONCE_PREFIX_SIZE,
+    STREAM_TAG_SIZE,
+    STREAM_VERSION
+};
+
+/// Header of a segmented stream: magic, format version, plaintext segment
+/// size and the random prefix every segment nonce starts with. The whole
+/// header is authenticated as associated data of every segment.
+#[derive(Debug, Clone, Copy)]
+pub struct StreamHeader {
+    pub segment_size: usize,
+    nonce_prefix: [u8; STREAM_NONCE_PREFIX_SIZE],
+}
+
+impl StreamHeader {
+    fn new(segment_size: usize) -> Self {
+        let mut nonce_prefix: [u8; STREAM_NONCE_PREFIX_SIZE] = [0u8; STREAM_NONCE_PREFIX_SIZE];
+        OsRng.fill_bytes(&mut nonce_prefix);
+
+        Self {
+            segment_size,
+            nonce_prefix,
+        }
+    }
+
+    fn to_bytes(self) -> [u8; STREAM_HEADER_SIZE] {
+        let mut bytes: [u8; STREAM_HEADER_SIZE] = [0u8; STREAM_HEADER_SIZE];
+        let magic_end: usize = STREAM_MAGIC.len();
+
+        bytes[..magic_end].copy_from_slice(STREAM_MAGIC);
+        bytes[magic_end] = STREAM_VERSION;
+        bytes[magic_end + 1..magic_end + 5].copy_from_slice(&(self.segment_size as u32).to_le_bytes());
+        bytes[magic_end + 5..].copy_from_slice(&self.nonce_prefix);
+
+        bytes
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self, SecurityError> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(STREAM_HEADER_SIZE);
+        let magic_end: usize = STREAM_MAGIC.len();
+
+        reader.take(STREAM_HEADER_SIZE as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < STREAM_HEADER_SIZE {
+            return Err(SecurityError::TooShort { length: bytes.len(), minimum: STREAM_HEADER_SIZE });
+        }
+        if &bytes[..magic_end] != STREAM_MAGIC {
+            return Err(SecurityError::UnknownFormat);
+        }
+        if bytes[magic_end] != STREAM_VERSION {
+            return Err(SecurityError::UnsupportedVersion(bytes[magic_end]));
+        }
+        let mut segment_size: [u8; 4] = [0u8; 4];
+        segment_size.copy_from_slice(&bytes[magic_end + 1..magic_end + 5]);
+        let segment_size: usize = u32::from_le_bytes(segment_size) as usize;
+        if segment_size == 0 || segment_size > STREAM_MAX_SEGMENT_SIZE {
+            return Err(SecurityError::UnknownFormat);
+        }
+        let mut nonce_prefix: [u8; STREAM_NONCE_PREFIX_SIZE] = [0u8; STREAM_NONCE_PREFIX_SIZE];
+        nonce_prefix.copy_from_slice(&bytes[magic_end + 5..]);
+
+        Ok(Self {
+            segment_size,
+            nonce_prefix,
+        })
+    }
+
+    pub fn sealed_segment_size(&self) -> usize {
+        self.segment_size + STREAM_TAG_SIZE
+    }
+
+    /// Nonce of segment `counter`: the stream prefix, the big-endian counter
+    /// and a flag set only on the last segment, so that dropping trailing
+    /// segments makes the stream fail to open.
+    fn nonce(&self, counter: u32, last: bool) -> [u8; 12] {
+        let mut nonce: [u8; 12] = [0u8; 12];
+
+        nonce[..STREAM_NONCE_PREFIX_SIZE].copy_from_slice(&self.nonce_prefix);
+        nonce[STREAM_NONCE_PREFIX_SIZE..11].copy_from_slice(&counter.to_be_bytes());
+        nonce[11] = last as u8;
+
+        nonce
+    }
+
+    fn seal(&self, cipher: &Aes256Gcm, counter: u32, last: bool, plaintext: &[u8]) -> Result<Vec<u8>, SecurityError> {
+        let aad: [u8; STREAM_HEADER_SIZE] = self.to_bytes();
+
+        cipher
+            .encrypt(Nonce::from_slice(&self.nonce(counter, last)), Payload { msg: plaintext, aad: &aad })
+            .map_err(|_| SecurityError::EncryptionFailed)
+    }
+
+    fn open(&self, cipher: &Aes256Gcm, counter: u32, last: bool, sealed: &[u8]) -> Result<Vec<u8>, SecurityError> {
+        if sealed.len() < STREAM_TAG_SIZE {
+            return Err(SecurityError::Truncated);
+        }
+        let aad: [u8; STREAM_HEADER_SIZE] = self.to_bytes();
+
+        cipher
+            .decrypt(Nonce::from_slice(&self.nonce(counter, last)), Payload { msg: sealed, aad: &aad })
+            .map_err(|_| SecurityError::AuthenticationFailed)
+    }
+}
+
+/// Encrypts everything written to it as a segmented stream, holding at
+/// most one plaintext segment in memory. `finish` seals the last segment;
+/// a stream dropped without it cannot be decrypted.
+pub struct StreamEncryptor<W: Write> {
+    cipher: Aes256Gcm,
+    header: StreamHeader,
+    writer: W,
+    buffer: Vec<u8>,
+    counter: u32,
+}
+
+impl<W: Write> StreamEncryptor<W> {
+    pub fn new(key: &Key<Aes256Gcm>, mut writer: W, segment_size: usize) -> Result<Self, SecurityError> {
+        let header: StreamHeader = StreamHeader::new(segment_size);
+        writer.write_all(&header.to_bytes())?;
+
+        Ok(Self {
+            cipher: Aes256Gcm::new(key),
+            header,
+            writer,
+            buffer: Vec::with_capacity(segment_size),
+            counter: 0,
+        })
+    }
+
+    fn seal_segment(&mut self, last: bool) -> Result<(), SecurityError> {
+        let sealed: Vec<u8> = self.header.seal(&self.cipher, self.counter, last, &self.buffer)?;
+
+        self.writer.write_all(&sealed)?;
+        self.buffer.clear();
+        self.counter = self.counter
+            .checked_add(1)
+            .ok_or(SecurityError::EncryptionFailed)?;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W, SecurityError> {
+        self.seal_segment(true)?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for StreamEncryptor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written: usize = 0;
+
+        while written < buf.len() {
+            // A full segment is only sealed once more data shows it is not
+            // the last one.
+            if self.buffer.len() == self.header.segment_size {
+                self.seal_segment(false)?;
+            }
+            let take: usize = std::cmp::min(self.header.segment_size - self.buffer.len(), buf.len() - written);
+            self.buffer.extend_from_slice(&buf[written..written + take]);
+            written += take;
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads back the plaintext of a segmented stream, one segment at a time.
+/// A stream that ends before its last segment, or continues after it, is
+/// reported as invalid data.
+pub struct StreamDecryptor<R: BufRead> {
+    cipher: Aes256Gcm,
+    header: StreamHeader,
+    reader: R,
+    segment: Vec<u8>,
+    position: usize,
+    counter: u32,
+    finished: bool,
+}
+
+impl<R: BufRead> StreamDecryptor<R> {
+    pub fn new(key: &Key<Aes256Gcm>, mut reader: R) -> Result<Self, SecurityError> {
+        let header: StreamHeader = StreamHeader::read(&mut reader)?;
+
+        Ok(Self {
+            cipher: Aes256Gcm::new(key),
+            header,
+            reader,
+            segment: Vec::new(),
+            position: 0,
+            counter: 0,
+            finished: false,
+        })
+    }
+
+    fn read_segment(&mut self) -> Result<(), SecurityError> {
+        let mut sealed: Vec<u8> = Vec::with_capacity(self.header.sealed_segment_size());
+        self.reader
+            .by_ref()
+            .take(self.header.sealed_segment_size() as u64)
+            .read_to_end(&mut sealed)?;
+        let last: bool = sealed.len() < self.header.sealed_segment_size() || self.reader.fill_buf()?.is_empty();
+
+        self.segment = self.header.open(&self.cipher, self.counter, last, &sealed)?;
+        self.position = 0;
+        self.finished = last;
+        self.counter = self.counter
+            .checked_add(1)
+            .ok_or(SecurityError::TrailingData)?;
+
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for StreamDecryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.segment.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.read_segment()?;
+        }
+        let take: usize = std::cmp::min(buf.len(), self.segment.len() - self.position);
+        buf[..take].copy_from_slice(&self.segment[self.position..self.position + take]);
+        self.position += take;
+
+        Ok(take)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEGMENT_SIZE: usize = 16;
+
+    fn key() -> Key<Aes256Gcm> {
+        *Key::<Aes256Gcm>::from_slice(&[7u8; 32])
+    }
+
+    fn encrypt(plaintext: &[u8]) -> Vec<u8> {
+        let mut encryptor: StreamEncryptor<Vec<u8>> = StreamEncryptor::new(&key(), Vec::new(), SEGMENT_SIZE).unwrap();
+        encryptor.write_all(plaintext).unwrap();
+
+        encryptor.finish().unwrap()
+    }
+
+    fn decrypt(sealed: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decryptor: StreamDecryptor<&[u8]> = StreamDecryptor::new(&key(), sealed).map_err(io::Error::from)?;
+        let mut plaintext: Vec<u8> = Vec::new();
+        decryptor.read_to_end(&mut plaintext)?;
+
+        Ok(plaintext)
+    }
+
+    fn plaintext(length: usize) -> Vec<u8> {
+        (0..length).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn round_trips_across_segment_edges() {
+        for length in [0, 1, SEGMENT_SIZE - 1, SEGMENT_SIZE, SEGMENT_SIZE + 1, SEGMENT_SIZE * 4, 1000] {
+            let plaintext: Vec<u8> = plaintext(length);
+
+            assert_eq!(decrypt(&encrypt(&plaintext)).unwrap(), plaintext, "{} bytes", length);
+        }
+    }
+
+    #[test]
+    fn detects_truncation() {
+        let sealed: Vec<u8> = encrypt(&plaintext(SEGMENT_SIZE * 4));
+        let sealed_segment: usize = SEGMENT_SIZE + STREAM_TAG_SIZE;
+        assert_eq!(sealed.len(), STREAM_HEADER_SIZE + 4 * sealed_segment);
+
+        for length in [STREAM_HEADER_SIZE - 1, STREAM_HEADER_SIZE + sealed_segment, STREAM_HEADER_SIZE + 3 * sealed_segment, sealed.len() - 1] {
+            assert!(decrypt(&sealed[..length]).is_err(), "stream cut at {} of {} bytes", length, sealed.len());
+        }
+    }
+
+    #[test]
+    fn detects_trailing_data() {
+        let sealed: Vec<u8> = encrypt(&plaintext(SEGMENT_SIZE * 2 + 5));
+        let sealed_segment: usize = SEGMENT_SIZE + STREAM_TAG_SIZE;
+        let mut extra_byte: Vec<u8> = sealed.clone();
+        extra_byte.push(0);
+        let mut repeated_segment: Vec<u8> = sealed.clone();
+        repeated_segment.extend_from_slice(&sealed[STREAM_HEADER_SIZE..STREAM_HEADER_SIZE + sealed_segment]);
+
+        assert!(decrypt(&extra_byte).is_err());
+        assert!(decrypt(&repeated_segment).is_err());
+    }
+
+    #[test]
+    fn rejects_a_tampered_header() {
+        let mut sealed: Vec<u8> = encrypt(&plaintext(40));
+        sealed[STREAM_HEADER_SIZE - 1] ^= 1;
+
+        assert!(decrypt(&sealed).is_err());
+    }
+}
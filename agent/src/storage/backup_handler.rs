@@ -0,0 +1,407 @@
+use chrono::{DateTime, Utc};
+use log::info;
+use rs_merkle::MerkleTree;
+use rs_merkle::algorithms::Sha256 as MerkleHasher;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::security::security::{SecurityError, SecurityHandler};
+use crate::storage::chunk_store::{ChunkStore, CollectOptions, CollectReport};
+use crate::storage::chunker::{Chunk, Chunker, ChunkerVersion};
+use crate::storage::signature_handler::Signature;
+use crate::utils::constants::{
+    VAULT_OBJECT_DIRECTORY,
+    VAULT_STAGING_DIRECTORY,
+    RESTORE_TEMPORARY_SUFFIX
+};
+
+/// Why a vault operation failed. Callers tell a copy that is damaged or
+/// cannot be opened with the configured keys apart from the vault or a
+/// file being unreadable.
+#[derive(Debug)]
+pub enum BackupError {
+    /// A chunk, manifest or data key failed to encrypt, decrypt or
+    /// authenticate.
+    Security { context: String, source: SecurityError },
+    /// The vault copy, or the content being backed up, does not match its
+    /// manifest, leaf hashes or stored signature.
+    Mismatch(String),
+    /// The vault or a file could not be read or written.
+    Io(String),
+}
+
+pub type BackupResult<T> = Result<T, BackupError>;
+
+impl BackupError {
+    pub fn security(context: impl Into<String>, source: SecurityError) -> Self {
+        BackupError::Security { context: context.into(), source }
+    }
+
+    /// The same error, its message prefixed with `context`.
+    pub fn context(self, context: impl fmt::Display) -> Self {
+        match self {
+            BackupError::Security { context: inner, source } => BackupError::Security { context: format!("{}: {}", context, inner), source },
+            BackupError::Mismatch(message) => BackupError::Mismatch(format!("{}: {}", context, message)),
+            BackupError::Io(message) => BackupError::Io(format!("{}: {}", context, message)),
+        }
+    }
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::Security { context, source } => write!(f, "{}: {}", context, source),
+            BackupError::Mismatch(message) | BackupError::Io(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+/// A worker that failed before the vault could be read or written.
+impl From<String> for BackupError {
+    fn from(message: String) -> Self {
+        BackupError::Io(message)
+    }
+}
+
+/// Describes one encrypted vault copy. The plaintext leaves tie the copy to
+/// the stored `Signature`, while the sealed leaves and root cover the
+/// encrypted bytes so the copy can be checked without the key.
+///
+/// Copies are kept in the chunk store as their ordered list of leaves, and
+/// the sealed leaves hash each stored chunk. Manifests are encrypted under
+/// a key derived from the store's data key and named after an HMAC of the
+/// file name, so the vault alone reveals neither file names nor leaves.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct VaultManifest {
+    pub file_name: String,
+    pub signature: String,
+    pub chunker_version: u32,
+    pub leaves: Vec<String>,
+    pub chunk_positions: Vec<usize>,
+    pub sealed_leaves: Vec<String>,
+    pub sealed_root: String,
+    pub created_at: String,
+}
+
+pub struct RestoreOptions {
+    /// Directory the files are restored into instead of the storage directory.
+    pub target_directory: Option<String>,
+    /// Restores the versions that were current at this time instead of
+    /// the latest ones.
+    pub at: Option<DateTime<Utc>>,
+    pub dry_run: bool,
+}
+
+pub struct BackupHandler {
+    vault_dir: String,
+    chunk_store: ChunkStore,
+}
+
+impl BackupHandler {
+    pub fn new(vault_dir: &str, security_handler: SecurityHandler) -> Self {
+        info!("Vault directory set: {}", vault_dir);
+        Self {
+            vault_dir: vault_dir.to_string(),
+            chunk_store: ChunkStore::new(vault_dir, security_handler),
+        }
+    }
+
+    /// Path of the manifest of `file_name`, or `None` while the chunk store
+    /// has no key to name it with.
+    fn manifest_path(&self, file_name: &str) -> BackupResult<Option<PathBuf>> {
+        Ok(self.chunk_store.manifest_name(file_name)?.map(|name| {
+            PathBuf::from(&self.vault_dir)
+                .join(VAULT_OBJECT_DIRECTORY)
+                .join(&name[..2])
+                .join(name)
+                .with_extension("manifest")
+        }))
+    }
+
+    fn merkle_root(leaves: &[[u8; 32]]) -> Option<[u8; 32]> {
+        MerkleTree::<MerkleHasher>::from_leaves(leaves).root()
+    }
+
+    /// Manifest of the copy of `file_name`, if it has one.
+    pub fn load_manifest(&self, file_name: &str) -> BackupResult<Option<VaultManifest>> {
+        let manifest_path: PathBuf = match self.manifest_path(file_name)? {
+            Some(manifest_path) => manifest_path,
+            None => return Ok(None),
+        };
+        let sealed: Vec<u8> = match fs::read(&manifest_path) {
+            Ok(sealed) => sealed,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(BackupError::Io(format!("Failed to open manifest: {}", e))),
+        };
+        let manifest: VaultManifest = self.open_manifest(&sealed)?;
+        if manifest.file_name != file_name {
+            return Err(BackupError::Mismatch(format!("Manifest of '{}' belongs to '{}'", file_name, manifest.file_name)));
+        }
+
+        Ok(Some(manifest))
+    }
+
+    fn open_manifest(&self, sealed: &[u8]) -> BackupResult<VaultManifest> {
+        let contents: Vec<u8> = self.chunk_store.open_manifest(sealed)?;
+
+        serde_json::from_slice(&contents).map_err(|e| BackupError::Mismatch(format!("Failed to parse manifest: {}", e)))
+    }
+
+    /// Leaves of every copy, read from the manifests in the vault.
+    pub fn manifest_leaves(&self) -> BackupResult<HashSet<String>> {
+        let object_directory: PathBuf = PathBuf::from(&self.vault_dir).join(VAULT_OBJECT_DIRECTORY);
+        let read_error = |e: io::Error| BackupError::Io(format!("Failed to list vault manifests: {}", e));
+        let mut leaves: HashSet<String> = HashSet::new();
+        let prefixes: fs::ReadDir = match fs::read_dir(&object_directory) {
+            Ok(prefixes) => prefixes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(leaves),
+            Err(e) => return Err(read_error(e)),
+        };
+
+        for prefix in prefixes {
+            let prefix: fs::DirEntry = prefix.map_err(read_error)?;
+            if !prefix.file_type().map_err(read_error)?.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(prefix.path()).map_err(read_error)? {
+                let path: PathBuf = entry.map_err(read_error)?.path();
+                if path.extension().is_none_or(|extension| extension != "manifest") {
+                    continue;
+                }
+                let manifest: VaultManifest = self.open_manifest(&fs::read(&path).map_err(read_error)?)
+                    .map_err(|e| e.context(path.display()))?;
+                leaves.extend(manifest.leaves);
+            }
+        }
+
+        Ok(leaves)
+    }
+
+    pub fn collect_garbage(&self, live: &HashSet<String>, options: &CollectOptions) -> BackupResult<CollectReport> {
+        self.chunk_store.collect_garbage(live, options)
+    }
+
+    /// Manifest of a signature version whose copy is no longer the latest
+    /// one, made from its leaves and the chunks the store still holds.
+    pub fn version_manifest(&self, stored: &Signature) -> BackupResult<VaultManifest> {
+        let mut sealed_leaves: Vec<[u8; 32]> = Vec::with_capacity(stored.leaves.len());
+        for (index, leaf) in stored.leaves.iter().enumerate() {
+            sealed_leaves.push(
+                self.chunk_store.sealed_hash(leaf)
+                    .map_err(|e| e.context(format!("Vault chunk {}", index)))?
+            );
+        }
+
+        Ok(VaultManifest {
+            file_name: stored.file_name.clone(),
+            signature: stored.signature.clone(),
+            chunker_version: stored.chunker_version,
+            leaves: stored.leaves.clone(),
+            chunk_positions: stored.chunk_positions.clone(),
+            sealed_leaves: sealed_leaves.iter().map(hex::encode).collect(),
+            sealed_root: Self::merkle_root(&sealed_leaves).map(hex::encode).unwrap_or_default(),
+            created_at: stored.created_at.clone().unwrap_or_default(),
+        })
+    }
+
+    /// Stores every chunk of `file_path` the chunk store does not hold yet.
+    /// The manifest is only published when the plaintext the chunks came
+    /// from matches `stored`, and replaces any older copy of the file.
+    pub fn backup_file(&self, file_name: &str, file_path: &str, stored: &Signature) -> BackupResult<VaultManifest> {
+        let version: ChunkerVersion = ChunkerVersion::from_version(stored.chunker_version)
+            .ok_or_else(|| BackupError::Mismatch(format!("Unknown chunker version: {}", stored.chunker_version)))?;
+        let source: File = File::open(file_path)
+            .map_err(|e| BackupError::Io(format!("Failed to open file: {}", e)))?;
+        let mut leaves: Vec<[u8; 32]> = Vec::new();
+        let mut chunk_positions: Vec<usize> = vec![0];
+        let mut sealed_leaves: Vec<[u8; 32]> = Vec::new();
+        let mut new_chunks: usize = 0;
+
+        for chunk in Chunker::with_data(BufReader::new(source), version) {
+            let chunk: Chunk = chunk.map_err(|e| BackupError::Io(format!("Failed to read file: {}", e)))?;
+            let (sealed_leaf, new): ([u8; 32], bool) = self.chunk_store.put(&hex::encode(chunk.hash), &chunk.data)?;
+
+            leaves.push(chunk.hash);
+            chunk_positions.push(chunk.offset + chunk.length);
+            sealed_leaves.push(sealed_leaf);
+            new_chunks += new as usize;
+        }
+
+        let root: String = Self::merkle_root(&leaves).map(hex::encode).unwrap_or_default();
+        if root != stored.signature {
+            return Err(BackupError::Mismatch(format!("File '{}' changed since it was verified", file_name)));
+        }
+        let manifest: VaultManifest = VaultManifest {
+            file_name: file_name.to_string(),
+            signature: root,
+            chunker_version: stored.chunker_version,
+            leaves: leaves.iter().map(hex::encode).collect(),
+            chunk_positions,
+            sealed_leaves: sealed_leaves.iter().map(hex::encode).collect(),
+            sealed_root: Self::merkle_root(&sealed_leaves).map(hex::encode).unwrap_or_default(),
+            created_at: Utc::now().to_rfc3339(),
+        };
+
+        self.write_manifest(&manifest)?;
+        info!(
+            "Backed up {} chunks of '{}' to the vault, {} of them new",
+            manifest.leaves.len(),
+            file_name,
+            new_chunks
+        );
+
+        Ok(manifest)
+    }
+
+    /// Whether the data key of the chunk store is wrapped under a previous
+    /// key.
+    pub fn needs_rotation(&self) -> bool {
+        self.chunk_store.needs_rotation().unwrap_or(false)
+    }
+
+    /// Rewraps the data key of the chunk store under the configured key,
+    /// and reports whether anything had to change. No chunk or manifest is
+    /// re-encrypted, and as the store has a single data key only the first
+    /// copy rotated is reported as such.
+    pub fn rotate_key(&self) -> BackupResult<bool> {
+        self.chunk_store.rotate_key()
+    }
+
+    fn write_manifest(&self, manifest: &VaultManifest) -> BackupResult<()> {
+        let contents: Vec<u8> = serde_json::to_vec_pretty(manifest)
+            .map_err(|e| BackupError::Io(format!("Failed to serialize manifest: {}", e)))?;
+        let sealed: Vec<u8> = self.chunk_store.seal_manifest(&contents)?;
+        let manifest_path: PathBuf = self.manifest_path(&manifest.file_name)?
+            .ok_or_else(|| BackupError::Mismatch("Chunk store key is missing".to_string()))?;
+        let temporary_path: PathBuf = manifest_path.with_extension("tmp");
+        if let Some(parent) = manifest_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| BackupError::Io(format!("Failed to create vault directory: {}", e)))?;
+        }
+
+        fs::write(&temporary_path, sealed)
+            .and_then(|_| fs::rename(&temporary_path, &manifest_path))
+            .map_err(|e| BackupError::Io(format!("Failed to write manifest: {}", e)))
+    }
+
+    /// Checks the encrypted copy against the sealed leaves of its manifest.
+    /// Nothing is decrypted, so this needs neither the key nor much memory.
+    pub fn verify_backup(&self, manifest: &VaultManifest) -> BackupResult<()> {
+        if manifest.sealed_leaves.len() != manifest.leaves.len() {
+            return Err(BackupError::Mismatch("Vault manifest lists a different number of sealed and plain leaves".to_string()));
+        }
+        let mut sealed_leaves: Vec<[u8; 32]> = Vec::with_capacity(manifest.sealed_leaves.len());
+
+        for (index, (leaf, expected)) in manifest.leaves.iter().zip(&manifest.sealed_leaves).enumerate() {
+            let sealed_leaf: [u8; 32] = self.chunk_store.sealed_hash(leaf)
+                .map_err(|e| e.context(format!("Vault chunk {}", index)))?;
+
+            if hex::encode(sealed_leaf) != *expected {
+                return Err(BackupError::Mismatch(format!("Vault chunk {} does not match its manifest", index)));
+            }
+            sealed_leaves.push(sealed_leaf);
+        }
+        let root: String = Self::merkle_root(&sealed_leaves).map(hex::encode).unwrap_or_default();
+        if root != manifest.sealed_root {
+            return Err(BackupError::Mismatch("Vault manifest root does not match its sealed leaves".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Decrypts a single chunk of a vault copy and checks it against the
+    /// plaintext leaf recorded for it.
+    pub fn read_chunk(&self, manifest: &VaultManifest, index: usize) -> BackupResult<Vec<u8>> {
+        let leaf: &String = manifest.leaves
+            .get(index)
+            .ok_or_else(|| BackupError::Mismatch(format!("Chunk {} is not in the vault copy", index)))?;
+
+        self.chunk_store.get(leaf)
+    }
+
+    /// Decrypts a vault copy into a staging file inside the vault and checks
+    /// every chunk and the Merkle root against `stored`. Only a copy that
+    /// passes is moved to `target_path`.
+    pub fn restore_file(&self, manifest: &VaultManifest, stored: &Signature, target_path: &Path) -> BackupResult<u64> {
+        if manifest.signature != stored.signature {
+            return Err(BackupError::Mismatch("Vault copy was made from a different version of the file".to_string()));
+        }
+        self.verify_backup(manifest)?;
+
+        let staging_name: String = self.chunk_store.manifest_name(&manifest.file_name)?
+            .ok_or_else(|| BackupError::Mismatch("Chunk store key is missing".to_string()))?;
+        let staging_directory: PathBuf = PathBuf::from(&self.vault_dir).join(VAULT_STAGING_DIRECTORY);
+        let staging_path: PathBuf = staging_directory.join(staging_name).with_extension("restore");
+        fs::create_dir_all(&staging_directory)
+            .map_err(|e| BackupError::Io(format!("Failed to create staging directory: {}", e)))?;
+
+        let size: u64 = match self.decrypt_copy(manifest, stored, &staging_path) {
+            Ok(size) => size,
+            Err(e) => {
+                let _ = fs::remove_file(&staging_path);
+                return Err(e);
+            }
+        };
+        let published = Self::publish(&staging_path, target_path);
+        let _ = fs::remove_file(&staging_path);
+        published?;
+        info!("Restored '{}' ({} bytes) to {}", manifest.file_name, size, target_path.display());
+
+        Ok(size)
+    }
+
+    fn decrypt_copy(&self, manifest: &VaultManifest, stored: &Signature, staging_path: &Path) -> BackupResult<u64> {
+        let write_error = |e: io::Error| BackupError::Io(format!("Failed to write staging file: {}", e));
+        let mut writer: BufWriter<File> = BufWriter::new(File::create(staging_path).map_err(write_error)?);
+        let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(manifest.leaves.len());
+        let mut size: u64 = 0;
+
+        for (index, expected) in manifest.leaves.iter().enumerate() {
+            let data: Vec<u8> = self.chunk_store.get(expected)
+                .map_err(|e| e.context(format!("Vault chunk {}", index)))?;
+
+            writer.write_all(&data).map_err(write_error)?;
+            leaves.push(Sha256::digest(&data).into());
+            size += data.len() as u64;
+        }
+        writer.into_inner()
+            .map_err(|e| write_error(e.into_error()))?
+            .sync_all()
+            .map_err(write_error)?;
+
+        let root: String = Self::merkle_root(&leaves).map(hex::encode).unwrap_or_default();
+        if root != stored.signature {
+            return Err(BackupError::Mismatch("Decrypted copy does not match the stored signature".to_string()));
+        }
+
+        Ok(size)
+    }
+
+    /// Moves a verified staging file into place. When the target is on
+    /// another filesystem the file is copied next to the target first, so
+    /// the final step is always an atomic rename.
+    fn publish(staging_path: &Path, target_path: &Path) -> BackupResult<()> {
+        if let Some(parent) = target_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| BackupError::Io(format!("Failed to create target directory: {}", e)))?;
+        }
+        if fs::rename(staging_path, target_path).is_ok() {
+            return Ok(());
+        }
+
+        let file_name: String = target_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let temporary_path: PathBuf = target_path.with_file_name(format!(".{}{}", file_name, RESTORE_TEMPORARY_SUFFIX));
+        let copied = fs::copy(staging_path, &temporary_path)
+            .and_then(|_| File::open(&temporary_path)?.sync_all())
+            .and_then(|_| fs::rename(&temporary_path, target_path));
+
+        copied.map_err(|e| {
+            let _ = fs::remove_file(&temporary_path);
+            BackupError::Io(format!("Failed to move restored file into place: {}", e))
+        })
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+
+use crate::storage::chunker::Chunk;
+use crate::utils::constants::CDC_DIFF_MAX_EDITS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkChangeKind {
+    Inserted,
+    Removed,
+    Modified,
+    Moved,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChunkChange {
+    pub kind: ChunkChangeKind,
+    pub original_index: Option<usize>,
+    pub original_range: Option<Range<usize>>,
+    pub current_index: Option<usize>,
+    pub current_range: Option<Range<usize>>,
+}
+
+impl fmt::Display for ChunkChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            ChunkChangeKind::Inserted => "inserted",
+            ChunkChangeKind::Removed => "removed",
+            ChunkChangeKind::Modified => "modified",
+            ChunkChangeKind::Moved => "moved",
+        };
+        write!(f, "{}", kind)?;
+        if let (Some(index), Some(range)) = (self.original_index, &self.original_range) {
+            write!(f, " original chunk {} [{}..{}]", index, range.start, range.end)?;
+        }
+        if let (Some(index), Some(range)) = (self.current_index, &self.current_range) {
+            write!(f, " current chunk {} [{}..{}]", index, range.start, range.end)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Equal,
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Shortest edit script between two leaf sequences (Myers, O((N+M)D)).
+/// Returns `None` when more than `max_edits` edits would be needed.
+fn shortest_edit(original: &[[u8; 32]], current: &[[u8; 32]], max_edits: usize) -> Option<Vec<Edit>> {
+    let n = original.len() as isize;
+    let m = current.len() as isize;
+    let max = std::cmp::min((n + m) as usize, max_edits) as isize;
+    let offset = max + 1;
+    let mut v: Vec<isize> = vec![0; (2 * max + 3) as usize];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    for d in 0..=max {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize]) {
+                v[(offset + k + 1) as usize]
+            } else {
+                v[(offset + k - 1) as usize] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && original[x as usize] == current[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(offset + k) as usize] = x;
+
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m));
+            }
+        }
+    }
+
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Edit> {
+    let mut edits: Vec<Edit> = Vec::new();
+    let mut x = n;
+    let mut y = m;
+
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+
+        while x > previous_x && y > previous_y {
+            edits.push(Edit::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == previous_x {
+                edits.push(Edit::Insert(previous_y as usize));
+            } else {
+                edits.push(Edit::Delete(previous_x as usize));
+            }
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    edits.reverse();
+
+    edits
+}
+
+/// Aligns the current chunks of a file against its recorded leaves and
+/// reports every chunk that was inserted, removed, modified or moved.
+pub fn diff_chunks(original_leaves: &[[u8; 32]], original_boundaries: &[usize], current_chunks: &[Chunk]) -> Vec<ChunkChange> {
+    let current_leaves: Vec<[u8; 32]> = current_chunks.iter().map(|chunk| chunk.hash).collect();
+    let prefix = original_leaves
+        .iter()
+        .zip(current_leaves.iter())
+        .take_while(|(original, current)| original == current)
+        .count();
+    let suffix = original_leaves[prefix..]
+        .iter()
+        .rev()
+        .zip(current_leaves[prefix..].iter().rev())
+        .take_while(|(original, current)| original == current)
+        .count();
+    let original_middle = &original_leaves[prefix..original_leaves.len() - suffix];
+    let current_middle = &current_leaves[prefix..current_leaves.len() - suffix];
+
+    let edits: Vec<Edit> = shortest_edit(original_middle, current_middle, CDC_DIFF_MAX_EDITS)
+        .unwrap_or_else(|| {
+            (0..original_middle.len()).map(Edit::Delete)
+                .chain((0..current_middle.len()).map(Edit::Insert))
+                .collect()
+        });
+
+    let mut runs: Vec<(Vec<usize>, Vec<usize>)> = vec![(Vec::new(), Vec::new())];
+    for edit in edits {
+        match edit {
+            Edit::Equal => {
+                if runs.last().is_some_and(|(removed, inserted)| !removed.is_empty() || !inserted.is_empty()) {
+                    runs.push((Vec::new(), Vec::new()));
+                }
+            }
+            Edit::Delete(index) => runs.last_mut().unwrap().0.push(prefix + index),
+            Edit::Insert(index) => runs.last_mut().unwrap().1.push(prefix + index),
+        }
+    }
+
+    let mut inserted_by_hash: HashMap<[u8; 32], Vec<usize>> = HashMap::new();
+    for (_, inserted) in &runs {
+        for &index in inserted.iter().rev() {
+            inserted_by_hash.entry(current_leaves[index]).or_default().push(index);
+        }
+    }
+
+    let original_range = |index: usize| match (original_boundaries.get(index), original_boundaries.get(index + 1)) {
+        (Some(&start), Some(&end)) => Some(start..end),
+        _ => None,
+    };
+    let current_range = |index: usize| {
+        let chunk = &current_chunks[index];
+        chunk.offset..chunk.offset + chunk.length
+    };
+    let change = |kind: ChunkChangeKind, original: Option<usize>, current: Option<usize>| ChunkChange {
+        kind,
+        original_index: original,
+        original_range: original.and_then(original_range),
+        current_index: current,
+        current_range: current.map(current_range),
+    };
+
+    let mut moved: Vec<ChunkChange> = Vec::new();
+    let mut moved_to: HashSet<usize> = HashSet::new();
+    for (removed, _) in runs.iter_mut() {
+        removed.retain(|&index| {
+            match inserted_by_hash.get_mut(&original_leaves[index]).and_then(|targets| targets.pop()) {
+                Some(target) => {
+                    moved.push(change(ChunkChangeKind::Moved, Some(index), Some(target)));
+                    moved_to.insert(target);
+                    false
+                }
+                None => true,
+            }
+        });
+    }
+
+    let mut changes: Vec<ChunkChange> = Vec::new();
+    for (removed, mut inserted) in runs {
+        inserted.retain(|index| !moved_to.contains(index));
+        let paired = std::cmp::min(removed.len(), inserted.len());
+
+        for (&original, &current) in removed.iter().zip(inserted.iter()) {
+            changes.push(change(ChunkChangeKind::Modified, Some(original), Some(current)));
+        }
+        for &original in &removed[paired..] {
+            changes.push(change(ChunkChangeKind::Removed, Some(original), None));
+        }
+        for &current in &inserted[paired..] {
+            changes.push(change(ChunkChangeKind::Inserted, None, Some(current)));
+        }
+    }
+    changes.extend(moved);
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNK_LENGTH: usize = 10;
+
+    fn leaves(names: &str) -> Vec<[u8; 32]> {
+        names.bytes().map(|name| [name; 32]).collect()
+    }
+
+    fn boundaries(names: &str) -> Vec<usize> {
+        (0..=names.len()).map(|index| index * CHUNK_LENGTH).collect()
+    }
+
+    fn chunks(names: &str) -> Vec<Chunk> {
+        leaves(names)
+            .into_iter()
+            .enumerate()
+            .map(|(index, hash)| Chunk { offset: index * CHUNK_LENGTH, length: CHUNK_LENGTH, hash, data: Vec::new() })
+            .collect()
+    }
+
+    fn diff(original: &str, current: &str) -> Vec<(ChunkChangeKind, Option<usize>, Option<usize>)> {
+        diff_chunks(&leaves(original), &boundaries(original), &chunks(current))
+            .into_iter()
+            .map(|change| (change.kind, change.original_index, change.current_index))
+            .collect()
+    }
+
+    #[test]
+    fn finds_the_shortest_edit_script() {
+        let edits: Vec<Edit> = shortest_edit(&leaves("ABCABBA"), &leaves("CBABAC"), CDC_DIFF_MAX_EDITS).unwrap();
+
+        assert_eq!(edits.iter().filter(|edit| **edit != Edit::Equal).count(), 5);
+        assert_eq!(edits.iter().filter(|edit| **edit == Edit::Equal).count(), 4);
+        assert!(shortest_edit(&leaves("ABCABBA"), &leaves("CBABAC"), 4).is_none());
+    }
+
+    #[test]
+    fn reports_nothing_for_identical_chunks() {
+        assert!(diff("ABCDE", "ABCDE").is_empty());
+    }
+
+    #[test]
+    fn aligns_shifted_chunks_instead_of_pairing_them_by_position() {
+        assert_eq!(
+            diff("ABCDE", "BCDEF"),
+            vec![(ChunkChangeKind::Removed, Some(0), None), (ChunkChangeKind::Inserted, None, Some(4))]
+        );
+        assert_eq!(diff("ABCDE", "ABXCDE"), vec![(ChunkChangeKind::Inserted, None, Some(2))]);
+        assert_eq!(diff("ABCDE", "ABDE"), vec![(ChunkChangeKind::Removed, Some(2), None)]);
+    }
+
+    #[test]
+    fn pairs_replaced_chunks_as_modified() {
+        assert_eq!(
+            diff("ABCDE", "ABXYE"),
+            vec![(ChunkChangeKind::Modified, Some(2), Some(2)), (ChunkChangeKind::Modified, Some(3), Some(3))]
+        );
+        assert_eq!(
+            diff("ABCDE", "AXYZE"),
+            vec![
+                (ChunkChangeKind::Modified, Some(1), Some(1)),
+                (ChunkChangeKind::Modified, Some(2), Some(2)),
+                (ChunkChangeKind::Modified, Some(3), Some(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_moved_chunks() {
+        assert_eq!(diff("ABCDE", "ACDEB"), vec![(ChunkChangeKind::Moved, Some(1), Some(4))]);
+    }
+
+    #[test]
+    fn reports_byte_ranges_of_both_sides() {
+        let changes: Vec<ChunkChange> = diff_chunks(&leaves("ABC"), &boundaries("ABC"), &chunks("AXC"));
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].original_range, Some(10..20));
+        assert_eq!(changes[0].current_range, Some(10..20));
+        assert_eq!(changes[0].to_string(), "modified original chunk 1 [10..20] current chunk 1 [10..20]");
+    }
+}
@@ -0,0 +1,653 @@
+use chrono::Utc;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use crate::security::security::{SecurityError, SecurityHandler};
+use crate::security::stream::{StreamDecryptor, StreamEncryptor};
+use crate::storage::backup_handler::{BackupError, BackupResult};
+use crate::utils::constants::{
+    CDC_MAX_CHUNK_SIZE,
+    ENCRYPTION_KEY_SIZE,
+    VAULT_CHUNK_DIRECTORY,
+    VAULT_CHUNK_KEY_DOMAIN,
+    VAULT_CHUNK_KEY_FILE,
+    VAULT_CHUNK_NAME_DOMAIN,
+    VAULT_CHUNK_TOMBSTONE_EXTENSION,
+    VAULT_MANIFEST_KEY_DOMAIN,
+    VAULT_MANIFEST_MAGIC,
+    VAULT_MANIFEST_NAME_DOMAIN,
+    VAULT_MANIFEST_VERSION
+};
+
+fn hash_file(path: &Path) -> io::Result<[u8; 32]> {
+    let mut file: File = File::open(path)?;
+    let mut hasher: Sha256 = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(hasher.finalize().into())
+}
+
+/// A chunk that is gone is part of a damaged copy, while any other
+/// failure to read it says nothing about the copy.
+fn read_error(leaf: &str, e: io::Error) -> BackupError {
+    if e.kind() == io::ErrorKind::NotFound {
+        return BackupError::Mismatch(format!("Chunk {} is missing", leaf));
+    }
+
+    BackupError::Io(format!("Failed to read chunk {}: {}", leaf, e))
+}
+
+fn is_leaf(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+fn age(path: &Path, now: SystemTime) -> io::Result<Duration> {
+    let modified: SystemTime = fs::metadata(path)?.modified()?;
+
+    Ok(now.duration_since(modified).unwrap_or(Duration::ZERO))
+}
+
+/// Marks a chunk as just used. Chunks younger than the grace period are
+/// never collected, which covers a backup until its manifest is written.
+fn touch(path: &Path) -> io::Result<()> {
+    OpenOptions::new().write(true).open(path)?.set_modified(SystemTime::now())
+}
+
+/// Puts a chunk moved aside by a collection back in place, unless a
+/// backup has written it again meanwhile.
+fn restore_tombstone(tombstone: &Path, chunk_path: &Path) -> io::Result<()> {
+    match fs::hard_link(tombstone, chunk_path) {
+        Ok(()) => fs::remove_file(tombstone),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => fs::remove_file(tombstone),
+        Err(e) => Err(e),
+    }
+}
+
+pub struct CollectOptions {
+    /// Unreferenced chunks modified more recently than this are kept.
+    pub grace_period: Duration,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct CollectReport {
+    pub live: usize,
+    pub recent: usize,
+    pub removed: usize,
+    pub removed_bytes: u64,
+    pub temporary: usize,
+    pub restored: usize,
+}
+
+/// Data key of the store, wrapped under the configured key so that a
+/// rotation only rewrites this one record. Every chunk key, chunk name and
+/// manifest key is derived from it.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct ChunkKey {
+    key_id: String,
+    wrapped_key: String,
+    created_at: String,
+}
+
+/// The unwrapped data key and the keys derived from it.
+struct StoreKeys {
+    data_key: [u8; ENCRYPTION_KEY_SIZE],
+    manifest: SecurityHandler,
+}
+
+impl StoreKeys {
+    fn new(data_key: [u8; ENCRYPTION_KEY_SIZE]) -> BackupResult<Self> {
+        let manifest_key: [u8; ENCRYPTION_KEY_SIZE] = Self::derive(&data_key, VAULT_MANIFEST_KEY_DOMAIN, &[])?;
+
+        Ok(Self {
+            data_key,
+            manifest: SecurityHandler::from_key(&manifest_key),
+        })
+    }
+
+    /// HKDF-SHA256 of the data key for `context` within `domain`.
+    fn derive(data_key: &[u8; ENCRYPTION_KEY_SIZE], domain: &[u8], context: &[u8]) -> BackupResult<[u8; ENCRYPTION_KEY_SIZE]> {
+        let mut key: [u8; ENCRYPTION_KEY_SIZE] = [0u8; ENCRYPTION_KEY_SIZE];
+        Hkdf::<Sha256>::new(None, data_key)
+            .expand_multi_info(&[domain, context], &mut key)
+            .map_err(|_| BackupError::security("Failed to derive a vault key", SecurityError::EncryptionFailed))?;
+
+        Ok(key)
+    }
+
+    /// HMAC-SHA256 of `value` within `domain`, keyed with the data key.
+    fn name(&self, domain: &[u8], value: &[u8]) -> BackupResult<String> {
+        let mut mac: Hmac<Sha256> = Hmac::<Sha256>::new_from_slice(&self.data_key)
+            .map_err(|_| BackupError::security("Failed to derive a vault name", SecurityError::EncryptionFailed))?;
+        mac.update(domain);
+        mac.update(value);
+
+        Ok(hex::encode(mac.finalize().into_bytes()))
+    }
+}
+
+/// Content-addressed store keeping every chunk once, whichever file and
+/// version it came from. Chunks are encrypted as segmented streams, each
+/// under a key derived from the store's data key and its own leaf hash, so
+/// the same chunk always gets the same key and name and is still stored
+/// once, while no two different chunks ever share a key.
+pub struct ChunkStore {
+    chunk_dir: PathBuf,
+    security_handler: SecurityHandler,
+    keys: OnceLock<StoreKeys>,
+}
+
+impl ChunkStore {
+    pub fn new(vault_dir: &str, security_handler: SecurityHandler) -> Self {
+        Self {
+            chunk_dir: PathBuf::from(vault_dir).join(VAULT_CHUNK_DIRECTORY),
+            security_handler,
+            keys: OnceLock::new(),
+        }
+    }
+
+    /// Chunks are named after an HMAC of their leaf, so that names reveal
+    /// nothing about the content.
+    fn chunk_name(&self, leaf: &str) -> BackupResult<String> {
+        if !is_leaf(leaf) {
+            return Err(BackupError::Mismatch(format!("Invalid chunk hash: {}", leaf)));
+        }
+
+        self.stored_keys()?.name(VAULT_CHUNK_NAME_DOMAIN, leaf.as_bytes())
+    }
+
+    fn chunk_path(&self, leaf: &str) -> BackupResult<PathBuf> {
+        let name: String = self.chunk_name(leaf)?;
+
+        Ok(self.chunk_dir.join(&name[..2]).join(name))
+    }
+
+    /// Cipher sealing the chunk with hash `leaf`, under a key derived for
+    /// that leaf.
+    fn chunk_cipher(&self, leaf: &str) -> BackupResult<SecurityHandler> {
+        StoreKeys::derive(&self.stored_keys()?.data_key, VAULT_CHUNK_KEY_DOMAIN, leaf.as_bytes())
+            .map(|key| SecurityHandler::from_key(&key))
+    }
+
+    fn key_path(&self) -> PathBuf {
+        self.chunk_dir.join(VAULT_CHUNK_KEY_FILE)
+    }
+
+    fn load_key(&self) -> BackupResult<Option<ChunkKey>> {
+        match fs::read(self.key_path()) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map(Some)
+                .map_err(|e| BackupError::Mismatch(format!("Failed to parse chunk store key: {}", e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(BackupError::Io(format!("Failed to read chunk store key: {}", e))),
+        }
+    }
+
+    /// Keys of the store, whose data key is created the first time
+    /// anything is stored.
+    fn keys(&self) -> BackupResult<&StoreKeys> {
+        if let Some(keys) = self.keys.get() {
+            return Ok(keys);
+        }
+        let chunk_key: ChunkKey = match self.load_key()? {
+            Some(chunk_key) => chunk_key,
+            None => self.create_key()?,
+        };
+
+        self.unwrap_keys(&chunk_key)
+    }
+
+    /// Keys of the store, or `None` while nothing was ever stored.
+    fn existing_keys(&self) -> BackupResult<Option<&StoreKeys>> {
+        if let Some(keys) = self.keys.get() {
+            return Ok(Some(keys));
+        }
+
+        match self.load_key()? {
+            Some(chunk_key) => self.unwrap_keys(&chunk_key).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Keys of the store for reading what it holds, which needs its data
+    /// key to exist already.
+    fn stored_keys(&self) -> BackupResult<&StoreKeys> {
+        self.existing_keys()?.ok_or_else(|| BackupError::Mismatch("Chunk store key is missing".to_string()))
+    }
+
+    fn unwrap_keys(&self, chunk_key: &ChunkKey) -> BackupResult<&StoreKeys> {
+        let data_key: [u8; ENCRYPTION_KEY_SIZE] = self.security_handler
+            .unwrap_key(&chunk_key.key_id, &chunk_key.wrapped_key)
+            .map_err(|e| BackupError::security("Failed to unwrap chunk store key", e))?;
+        let keys: StoreKeys = StoreKeys::new(data_key)?;
+
+        Ok(self.keys.get_or_init(|| keys))
+    }
+
+    /// Name the manifest of `file_name` is kept under, an HMAC of the file
+    /// name, or `None` while nothing was ever stored.
+    pub fn manifest_name(&self, file_name: &str) -> BackupResult<Option<String>> {
+        match self.existing_keys()? {
+            Some(keys) => keys.name(VAULT_MANIFEST_NAME_DOMAIN, file_name.as_bytes()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Encrypts a manifest under the manifest key, behind a format marker.
+    pub fn seal_manifest(&self, contents: &[u8]) -> BackupResult<Vec<u8>> {
+        let sealed: Vec<u8> = self.keys()?
+            .manifest
+            .encrypt(contents)
+            .map_err(|e| BackupError::security("Failed to encrypt manifest", e))?;
+        let mut bytes: Vec<u8> = VAULT_MANIFEST_MAGIC.to_vec();
+        bytes.push(VAULT_MANIFEST_VERSION);
+        bytes.extend_from_slice(&sealed);
+
+        Ok(bytes)
+    }
+
+    pub fn open_manifest(&self, bytes: &[u8]) -> BackupResult<Vec<u8>> {
+        let sealed: &[u8] = bytes
+            .strip_prefix(VAULT_MANIFEST_MAGIC)
+            .ok_or_else(|| BackupError::Mismatch("Not a sealed manifest".to_string()))?;
+        let sealed: &[u8] = match sealed.split_first() {
+            Some((&VAULT_MANIFEST_VERSION, sealed)) => sealed,
+            Some((&version, _)) => return Err(BackupError::security("Failed to open manifest", SecurityError::UnsupportedVersion(version))),
+            None => return Err(BackupError::Mismatch("Not a sealed manifest".to_string())),
+        };
+        self.stored_keys()?
+            .manifest
+            .decrypt(sealed)
+            .map_err(|e| BackupError::security("Failed to decrypt manifest", e))
+    }
+
+    fn create_key(&self) -> BackupResult<ChunkKey> {
+        let data_key: [u8; ENCRYPTION_KEY_SIZE] = SecurityHandler::generate_data_key();
+        let chunk_key: ChunkKey = ChunkKey {
+            key_id: self.security_handler.key_id().to_string(),
+            wrapped_key: self.security_handler.wrap_key(&data_key)
+                .map_err(|e| BackupError::security("Failed to wrap chunk store key", e))?,
+            created_at: Utc::now().to_rfc3339(),
+        };
+        let contents: Vec<u8> = serde_json::to_vec_pretty(&chunk_key)
+            .map_err(|e| BackupError::Io(format!("Failed to serialize chunk store key: {}", e)))?;
+
+        fs::create_dir_all(&self.chunk_dir)
+            .map_err(|e| BackupError::Io(format!("Failed to create chunk store: {}", e)))?;
+        let created = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(self.key_path())
+            .and_then(|mut file| file.write_all(&contents).and_then(|_| file.sync_all()));
+
+        match created {
+            Ok(()) => {
+                info!("Created chunk store key under key {}", chunk_key.key_id);
+                Ok(chunk_key)
+            }
+            // Another run created the key first, and its chunks use that one.
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => self.load_key()?
+                .ok_or_else(|| BackupError::Io("Chunk store key disappeared".to_string())),
+            Err(e) => Err(BackupError::Io(format!("Failed to write chunk store key: {}", e))),
+        }
+    }
+
+    pub fn needs_rotation(&self) -> BackupResult<bool> {
+        Ok(self.load_key()?.is_some_and(|chunk_key| chunk_key.key_id != self.security_handler.key_id()))
+    }
+
+    /// Rewraps the store's data key under the configured key.
+    pub fn rotate_key(&self) -> BackupResult<bool> {
+        let chunk_key: ChunkKey = match self.load_key()? {
+            Some(chunk_key) if chunk_key.key_id != self.security_handler.key_id() => chunk_key,
+            _ => return Ok(false),
+        };
+        let data_key: [u8; ENCRYPTION_KEY_SIZE] = self.security_handler
+            .unwrap_key(&chunk_key.key_id, &chunk_key.wrapped_key)
+            .map_err(|e| BackupError::security("Failed to unwrap chunk store key", e))?;
+        let rotated: ChunkKey = ChunkKey {
+            key_id: self.security_handler.key_id().to_string(),
+            wrapped_key: self.security_handler.wrap_key(&data_key)
+                .map_err(|e| BackupError::security("Failed to wrap chunk store key", e))?,
+            created_at: chunk_key.created_at,
+        };
+        let contents: Vec<u8> = serde_json::to_vec_pretty(&rotated)
+            .map_err(|e| BackupError::Io(format!("Failed to serialize chunk store key: {}", e)))?;
+        let temporary_path: PathBuf = self.key_path().with_extension("json.tmp");
+
+        fs::write(&temporary_path, contents)
+            .and_then(|_| fs::rename(&temporary_path, self.key_path()))
+            .map_err(|e| BackupError::Io(format!("Failed to write chunk store key: {}", e)))?;
+        info!("Rewrapped the chunk store key under key {}", rotated.key_id);
+        Ok(true)
+    }
+
+    /// Hash of the encrypted chunk as stored, checkable without decrypting.
+    pub fn sealed_hash(&self, leaf: &str) -> BackupResult<[u8; 32]> {
+        hash_file(&self.chunk_path(leaf)?).map_err(|e| read_error(leaf, e))
+    }
+
+    /// Stores `data` under `leaf` unless an intact copy is already there,
+    /// and returns the sealed hash of the stored chunk and whether it was
+    /// newly written.
+    pub fn put(&self, leaf: &str, data: &[u8]) -> BackupResult<([u8; 32], bool)> {
+        // The data key is created along with the first chunk stored.
+        self.keys()?;
+        let chunk_path: PathBuf = self.chunk_path(leaf)?;
+        if hex::encode(Sha256::digest(data)) != leaf {
+            return Err(BackupError::Mismatch(format!("Chunk content does not match its hash {}", leaf)));
+        }
+        if chunk_path.exists() {
+            // Refreshed before it is checked, so that a collection running
+            // meanwhile either sees it as recent or makes it disappear here.
+            match touch(&chunk_path).map_err(|e| read_error(leaf, e)).and_then(|_| self.get(leaf)) {
+                Ok(_) => return Ok((self.sealed_hash(leaf)?, false)),
+                Err(e) => warn!("Replacing damaged chunk {}: {}", leaf, e),
+            }
+        }
+
+        let temporary_path: PathBuf = chunk_path.with_extension(format!("{}.tmp", std::process::id()));
+        if let Some(parent) = chunk_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| BackupError::Io(format!("Failed to create chunk directory: {}", e)))?;
+        }
+        let written: BackupResult<[u8; 32]> = self.write_chunk(&temporary_path, &self.chunk_cipher(leaf)?, data)
+            .and_then(|sealed_hash| {
+                fs::rename(&temporary_path, &chunk_path)
+                    .map_err(|e| BackupError::Io(format!("Failed to publish chunk {}: {}", leaf, e)))?;
+                Ok(sealed_hash)
+            });
+        if written.is_err() {
+            let _ = fs::remove_file(&temporary_path);
+        }
+
+        written.map(|sealed_hash| (sealed_hash, true))
+    }
+
+    fn write_chunk(&self, path: &Path, cipher: &SecurityHandler, data: &[u8]) -> BackupResult<[u8; 32]> {
+        let write_error = |e: io::Error| BackupError::Io(format!("Failed to write chunk: {}", e));
+        let encrypt_error = |e: SecurityError| BackupError::security("Failed to encrypt chunk", e);
+        let file: File = File::create(path).map_err(write_error)?;
+        let mut encryptor: StreamEncryptor<BufWriter<File>> = cipher
+            .stream_encryptor(BufWriter::new(file))
+            .map_err(encrypt_error)?;
+
+        encryptor.write_all(data).map_err(|e| encrypt_error(e.into()))?;
+        encryptor.finish()
+            .map_err(encrypt_error)?
+            .into_inner()
+            .map_err(|e| write_error(e.into_error()))?
+            .sync_all()
+            .map_err(write_error)?;
+
+        hash_file(path).map_err(|e| BackupError::Io(format!("Failed to read chunk: {}", e)))
+    }
+
+    /// Decrypts the chunk stored under `leaf` and checks it against the hash.
+    pub fn get(&self, leaf: &str) -> BackupResult<Vec<u8>> {
+        let decrypt_error = |e: SecurityError| BackupError::security(format!("Failed to decrypt chunk {}", leaf), e);
+        let file: File = File::open(self.chunk_path(leaf)?)
+            .map_err(|e| read_error(leaf, e))?;
+        let mut decryptor: StreamDecryptor<BufReader<File>> = self.chunk_cipher(leaf)?
+            .stream_decryptor(BufReader::new(file))
+            .map_err(decrypt_error)?;
+        let mut data: Vec<u8> = Vec::new();
+
+        decryptor
+            .by_ref()
+            .take(CDC_MAX_CHUNK_SIZE as u64 + 1)
+            .read_to_end(&mut data)
+            .map_err(|e| decrypt_error(e.into()))?;
+        if data.len() > CDC_MAX_CHUNK_SIZE {
+            return Err(BackupError::Mismatch(format!("Chunk {} is larger than any chunk", leaf)));
+        }
+        if hex::encode(Sha256::digest(&data)) != leaf {
+            return Err(BackupError::Mismatch(format!("Chunk {} does not match its hash", leaf)));
+        }
+
+        Ok(data)
+    }
+
+    /// Sweeps the chunks that no leaf in `live` refers to. `live` has to
+    /// hold every leaf of every kept file version, as a chunk missing from
+    /// it is deleted once it is older than the grace period.
+    pub fn collect_garbage(&self, live: &HashSet<String>, options: &CollectOptions) -> BackupResult<CollectReport> {
+        let mut report: CollectReport = CollectReport::default();
+        let prefixes: fs::ReadDir = match fs::read_dir(&self.chunk_dir) {
+            Ok(prefixes) => prefixes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(report),
+            Err(e) => return Err(BackupError::Io(format!("Failed to list chunk store: {}", e))),
+        };
+        let mut live_names: HashSet<String> = HashSet::with_capacity(live.len());
+        if let Some(keys) = self.existing_keys()? {
+            for leaf in live {
+                live_names.insert(keys.name(VAULT_CHUNK_NAME_DOMAIN, leaf.as_bytes())?);
+            }
+        }
+        let list_error = |e: io::Error| BackupError::Io(format!("Failed to list chunk store: {}", e));
+        let now: SystemTime = SystemTime::now();
+
+        for prefix in prefixes {
+            let prefix: fs::DirEntry = prefix.map_err(list_error)?;
+            if !prefix.file_type().map_err(list_error)?.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(prefix.path()).map_err(list_error)? {
+                let path: PathBuf = entry.map_err(list_error)?.path();
+                let name: String = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                let age: Duration = age(&path, now).map_err(list_error)?;
+
+                if is_leaf(&name) {
+                    if live_names.contains(&name) {
+                        report.live += 1;
+                    } else if age < options.grace_period {
+                        report.recent += 1;
+                    } else {
+                        let size: u64 = fs::metadata(&path).map_err(list_error)?.len();
+                        if options.dry_run {
+                            info!("Would remove unreferenced chunk {}", name);
+                        } else if !self.remove_chunk(&path, options.grace_period)? {
+                            report.recent += 1;
+                            continue;
+                        }
+                        report.removed += 1;
+                        report.removed_bytes += size;
+                    }
+                } else if path.extension().is_some_and(|extension| extension == VAULT_CHUNK_TOMBSTONE_EXTENSION) {
+                    // Left behind by a collection that stopped before it
+                    // could remove or restore the chunk.
+                    if !options.dry_run {
+                        restore_tombstone(&path, &path.with_extension(""))
+                            .map_err(|e| BackupError::Io(format!("Failed to restore chunk {}: {}", name, e)))?;
+                    }
+                    report.restored += 1;
+                } else if path.extension().is_some_and(|extension| extension == "tmp") && age >= options.grace_period {
+                    // Left behind by a backup that stopped while writing.
+                    if !options.dry_run {
+                        fs::remove_file(&path).map_err(|e| BackupError::Io(format!("Failed to remove {}: {}", name, e)))?;
+                    }
+                    report.temporary += 1;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Moves a chunk aside and only removes it if it was still not used
+    /// once moved. A backup reusing the chunk at that moment either
+    /// refreshed it first, and it is put back, or finds it missing and
+    /// writes it again.
+    fn remove_chunk(&self, chunk_path: &Path, grace_period: Duration) -> BackupResult<bool> {
+        let tombstone: PathBuf = chunk_path.with_extension(VAULT_CHUNK_TOMBSTONE_EXTENSION);
+        let remove_error = |e: io::Error| BackupError::Io(format!("Failed to remove chunk {}: {}", chunk_path.display(), e));
+
+        match fs::rename(chunk_path, &tombstone) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(remove_error(e)),
+        }
+        if age(&tombstone, SystemTime::now()).map_err(remove_error)? < grace_period {
+            restore_tombstone(&tombstone, chunk_path).map_err(remove_error)?;
+            return Ok(false);
+        }
+        fs::remove_file(&tombstone).map_err(remove_error)?;
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    /// Vault directory removed once the test is over.
+    struct TestVault(PathBuf);
+
+    impl TestVault {
+        fn new(name: &str) -> Self {
+            let path: PathBuf = std::env::temp_dir().join(format!("glacier-chunk-store-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+
+            Self(path)
+        }
+
+        fn store(&self) -> ChunkStore {
+            ChunkStore::new(&self.0.to_string_lossy(), SecurityHandler::from_key(&[3u8; ENCRYPTION_KEY_SIZE]))
+        }
+    }
+
+    impl Drop for TestVault {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn leaf(data: &[u8]) -> String {
+        hex::encode(Sha256::digest(data))
+    }
+
+    fn put(store: &ChunkStore, data: &[u8]) -> (String, PathBuf) {
+        let leaf: String = leaf(data);
+        store.put(&leaf, data).unwrap();
+        let path: PathBuf = store.chunk_path(&leaf).unwrap();
+
+        (leaf, path)
+    }
+
+    fn make_old(path: &Path) {
+        File::options().write(true).open(path).unwrap().set_modified(SystemTime::now() - 2 * HOUR).unwrap();
+    }
+
+    fn collect(store: &ChunkStore, live: &[&String], dry_run: bool) -> CollectReport {
+        let live: HashSet<String> = live.iter().map(|leaf| leaf.to_string()).collect();
+
+        store.collect_garbage(&live, &CollectOptions { grace_period: HOUR, dry_run }).unwrap()
+    }
+
+    #[test]
+    fn stores_chunks_once_under_keyed_names() {
+        let vault: TestVault = TestVault::new("put");
+        let store: ChunkStore = vault.store();
+        let data: Vec<u8> = b"chunk content".to_vec();
+        let leaf: String = leaf(&data);
+
+        let (sealed_hash, new) = store.put(&leaf, &data).unwrap();
+        assert!(new);
+        assert_eq!(store.put(&leaf, &data).unwrap(), (sealed_hash, false));
+        assert_eq!(store.get(&leaf).unwrap(), data);
+        assert_eq!(store.sealed_hash(&leaf).unwrap(), sealed_hash);
+        assert_ne!(store.chunk_name(&leaf).unwrap(), leaf);
+        assert!(store.put(&leaf, b"other content").is_err());
+    }
+
+    #[test]
+    fn seals_manifests() {
+        let vault: TestVault = TestVault::new("manifest");
+        let store: ChunkStore = vault.store();
+        assert_eq!(store.manifest_name("a.bin").unwrap(), None);
+
+        let mut sealed: Vec<u8> = store.seal_manifest(b"{\"file_name\":\"a.bin\"}").unwrap();
+        assert_eq!(store.open_manifest(&sealed).unwrap(), b"{\"file_name\":\"a.bin\"}");
+        assert!(store.manifest_name("a.bin").unwrap().is_some_and(|name| is_leaf(&name)));
+        assert_ne!(store.manifest_name("a.bin").unwrap(), store.manifest_name("b.bin").unwrap());
+
+        let last: usize = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(store.open_manifest(&sealed).is_err());
+    }
+
+    #[test]
+    fn keeps_live_and_recent_chunks_and_removes_old_unreferenced_ones() {
+        let vault: TestVault = TestVault::new("collect");
+        let store: ChunkStore = vault.store();
+        let (live, live_path) = put(&store, b"live");
+        let (_, recent_path) = put(&store, b"recent");
+        let (_, old_path) = put(&store, b"old");
+        make_old(&live_path);
+        make_old(&old_path);
+
+        let report: CollectReport = collect(&store, &[&live], true);
+        assert_eq!((report.live, report.recent, report.removed), (1, 1, 1));
+        assert!(old_path.exists(), "a dry run removes nothing");
+
+        let report: CollectReport = collect(&store, &[&live], false);
+        assert_eq!((report.live, report.recent, report.removed), (1, 1, 1));
+        assert!(live_path.exists());
+        assert!(recent_path.exists());
+        assert!(!old_path.exists());
+        assert!(!old_path.with_extension(VAULT_CHUNK_TOMBSTONE_EXTENSION).exists());
+    }
+
+    #[test]
+    fn puts_back_chunks_used_while_being_removed() {
+        let vault: TestVault = TestVault::new("tombstone");
+        let store: ChunkStore = vault.store();
+        let (_, path) = put(&store, b"reused");
+        let tombstone: PathBuf = path.with_extension(VAULT_CHUNK_TOMBSTONE_EXTENSION);
+
+        // Refreshed by a backup after the collection listed it as old.
+        assert!(!store.remove_chunk(&path, HOUR).unwrap());
+        assert!(path.exists());
+        assert!(!tombstone.exists());
+
+        make_old(&path);
+        assert!(store.remove_chunk(&path, HOUR).unwrap());
+        assert!(!path.exists());
+        assert!(!tombstone.exists());
+    }
+
+    #[test]
+    fn restores_tombstones_left_by_an_interrupted_collection() {
+        let vault: TestVault = TestVault::new("interrupted");
+        let store: ChunkStore = vault.store();
+        let (leaf, path) = put(&store, b"moved aside");
+        let (_, rewritten_path) = put(&store, b"written again");
+        let tombstone: PathBuf = path.with_extension(VAULT_CHUNK_TOMBSTONE_EXTENSION);
+        let rewritten_tombstone: PathBuf = rewritten_path.with_extension(VAULT_CHUNK_TOMBSTONE_EXTENSION);
+        fs::rename(&path, &tombstone).unwrap();
+        fs::copy(&rewritten_path, &rewritten_tombstone).unwrap();
+        let stale: PathBuf = path.with_extension("1.tmp");
+        fs::write(&stale, b"partial").unwrap();
+        make_old(&stale);
+
+        let report: CollectReport = collect(&store, &[], true);
+        assert_eq!((report.restored, report.temporary), (2, 1));
+        assert!(tombstone.exists() && stale.exists(), "a dry run changes nothing");
+
+        let report: CollectReport = collect(&store, &[], false);
+        assert_eq!((report.restored, report.temporary), (2, 1));
+        assert!(!tombstone.exists() && !rewritten_tombstone.exists() && !stale.exists());
+        assert_eq!(store.get(&leaf).unwrap(), b"moved aside");
+        assert_eq!(store.get(&self::leaf(b"written again")).unwrap(), b"written again");
+    }
+}
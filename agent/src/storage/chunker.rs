@@ -0,0 +1,451 @@
+use std::io::{self, Read};
+use sha2::{Digest, Sha256};
+
+use crate::utils::constants::{
+    CDC_WINDOW_SIZE,
+    CDC_AVERAGE_CHUNK_SIZE,
+    CDC_MIN_CHUNK_SIZE,
+    CDC_MAX_CHUNK_SIZE,
+    CDC_MASK,
+    CDC_READ_BUFFER_SIZE,
+    CDC_GEAR_SEED,
+    CDC_NORMALIZATION_LEVEL,
+    CDC_CHUNKER_VERSION_ADDITIVE,
+    CDC_CHUNKER_VERSION_GEAR
+};
+
+const GEAR_TABLE: [u64; 256] = gear_table(CDC_GEAR_SEED);
+const GEAR_MASK_BITS: u32 = CDC_AVERAGE_CHUNK_SIZE.trailing_zeros();
+const GEAR_MASK_SMALL: u64 = gear_mask(GEAR_MASK_BITS + CDC_NORMALIZATION_LEVEL);
+const GEAR_MASK_LARGE: u64 = gear_mask(GEAR_MASK_BITS - CDC_NORMALIZATION_LEVEL);
+
+/// Fills the Gear table with splitmix64 output so that every build of the
+/// agent cuts files at the same places.
+const fn gear_table(seed: u64) -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state = seed;
+    let mut i = 0;
+
+    while i < table.len() {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+
+    table
+}
+
+/// Mask over the top `bits` bits of the Gear hash, which are the ones mixed
+/// from the longest run of preceding bytes.
+const fn gear_mask(bits: u32) -> u64 {
+    ((1u64 << bits) - 1) << (64 - bits)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkerVersion {
+    /// Sum of the bytes in a `CDC_WINDOW_SIZE` window, kept to verify
+    /// signatures recorded before the Gear chunker was introduced.
+    Additive,
+    /// Gear rolling hash with FastCDC normalized chunking.
+    Gear,
+}
+
+impl ChunkerVersion {
+    pub fn from_version(version: u32) -> Option<Self> {
+        match version {
+            CDC_CHUNKER_VERSION_ADDITIVE => Some(Self::Additive),
+            CDC_CHUNKER_VERSION_GEAR => Some(Self::Gear),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub offset: usize,
+    pub length: usize,
+    pub hash: [u8; 32],
+    /// Chunk content, only filled by a chunker built with `with_data`.
+    pub data: Vec<u8>,
+}
+
+/// Content-defined chunker reading its source through a fixed-size buffer,
+/// so memory use does not depend on the size of the file being chunked.
+pub struct Chunker<R: Read> {
+    reader: R,
+    version: ChunkerVersion,
+    buffer: Vec<u8>,
+    buffer_position: usize,
+    buffer_length: usize,
+    offset: usize,
+    chunk_offset: usize,
+    chunk_hasher: Sha256,
+    chunk_data: Option<Vec<u8>>,
+    window: [u8; CDC_WINDOW_SIZE],
+    window_hash: u32,
+    window_count: usize,
+    gear_hash: u64,
+    finished: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    pub fn new(reader: R, version: ChunkerVersion) -> Self {
+        Self {
+            reader,
+            version,
+            buffer: vec![0u8; CDC_READ_BUFFER_SIZE],
+            buffer_position: 0,
+            buffer_length: 0,
+            offset: 0,
+            chunk_offset: 0,
+            chunk_hasher: Sha256::new(),
+            chunk_data: None,
+            window: [0u8; CDC_WINDOW_SIZE],
+            window_hash: 0,
+            window_count: 0,
+            gear_hash: 0,
+            finished: false,
+        }
+    }
+
+    /// Chunker that also hands out the content of every chunk, which is
+    /// never larger than `CDC_MAX_CHUNK_SIZE`.
+    pub fn with_data(reader: R, version: ChunkerVersion) -> Self {
+        let mut chunker = Self::new(reader, version);
+        chunker.chunk_data = Some(Vec::with_capacity(CDC_MAX_CHUNK_SIZE));
+
+        chunker
+    }
+
+    fn fill_buffer(&mut self) -> io::Result<bool> {
+        loop {
+            match self.reader.read(&mut self.buffer) {
+                Ok(0) => return Ok(false),
+                Ok(read) => {
+                    self.buffer_position = 0;
+                    self.buffer_length = read;
+                    return Ok(true);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Feeds one byte to the rolling hash and reports whether a chunk
+    /// boundary falls right after it.
+    fn roll(&mut self, byte: u8) -> bool {
+        match self.version {
+            ChunkerVersion::Additive => self.roll_additive(byte),
+            ChunkerVersion::Gear => self.roll_gear(byte),
+        }
+    }
+
+    fn roll_additive(&mut self, byte: u8) -> bool {
+        let slot = self.offset % CDC_WINDOW_SIZE;
+
+        self.window_hash = self.window_hash.wrapping_add(byte as u32);
+        if self.offset >= CDC_WINDOW_SIZE {
+            self.window_hash = self.window_hash.wrapping_sub(self.window[slot] as u32);
+        }
+        self.window[slot] = byte;
+        self.offset += 1;
+
+        if self.offset < CDC_WINDOW_SIZE {
+            return false;
+        }
+        self.window_count += 1;
+        if self.window_count >= CDC_MIN_CHUNK_SIZE
+            && ((self.window_hash & CDC_MASK) == 0 || self.window_count >= CDC_MAX_CHUNK_SIZE)
+        {
+            self.window_count = 0;
+            return true;
+        }
+
+        false
+    }
+
+    fn roll_gear(&mut self, byte: u8) -> bool {
+        self.gear_hash = (self.gear_hash << 1).wrapping_add(GEAR_TABLE[byte as usize]);
+        self.offset += 1;
+
+        let chunk_size = self.offset - self.chunk_offset;
+        if chunk_size < CDC_MIN_CHUNK_SIZE {
+            return false;
+        }
+        let mask = if chunk_size < CDC_AVERAGE_CHUNK_SIZE {
+            GEAR_MASK_SMALL
+        } else {
+            GEAR_MASK_LARGE
+        };
+        if (self.gear_hash & mask) == 0 || chunk_size >= CDC_MAX_CHUNK_SIZE {
+            self.gear_hash = 0;
+            return true;
+        }
+
+        false
+    }
+
+    fn finish_chunk(&mut self) -> Chunk {
+        let hasher = std::mem::replace(&mut self.chunk_hasher, Sha256::new());
+        let data = match self.chunk_data.as_mut() {
+            Some(data) => std::mem::replace(data, Vec::with_capacity(CDC_MAX_CHUNK_SIZE)),
+            None => Vec::new(),
+        };
+        let chunk = Chunk {
+            offset: self.chunk_offset,
+            length: self.offset - self.chunk_offset,
+            hash: hasher.finalize().into(),
+            data,
+        };
+        self.chunk_offset = self.offset;
+
+        chunk
+    }
+
+    fn consume(&mut self, start: usize) {
+        let bytes = &self.buffer[start..self.buffer_position];
+
+        self.chunk_hasher.update(bytes);
+        if let Some(data) = self.chunk_data.as_mut() {
+            data.extend_from_slice(bytes);
+        }
+    }
+
+    fn next_chunk(&mut self) -> io::Result<Option<Chunk>> {
+        loop {
+            if self.buffer_position == self.buffer_length && !self.fill_buffer()? {
+                self.finished = true;
+                if self.offset > self.chunk_offset {
+                    return Ok(Some(self.finish_chunk()));
+                }
+                return Ok(None);
+            }
+
+            let start = self.buffer_position;
+            while self.buffer_position < self.buffer_length {
+                let byte = self.buffer[self.buffer_position];
+                self.buffer_position += 1;
+                if self.roll(byte) {
+                    self.consume(start);
+                    return Ok(Some(self.finish_chunk()));
+                }
+            }
+            self.consume(start);
+        }
+    }
+}
+
+impl<R: Read> Iterator for Chunker<R> {
+    type Item = io::Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.next_chunk() {
+            Ok(Some(chunk)) => Some(Ok(chunk)),
+            Ok(None) => None,
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Hashes the byte ranges delimited by previously recorded boundaries.
+/// A file shorter than the recorded boundaries yields a truncated last
+/// chunk, and bytes past the last boundary are hashed as one extra chunk.
+pub fn hash_ranges<R: Read>(mut reader: R, boundaries: &[usize]) -> io::Result<Vec<Chunk>> {
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut offset: usize = boundaries.first().copied().unwrap_or(0);
+
+    for window in boundaries.windows(2) {
+        let expected = window[1].saturating_sub(window[0]);
+        let mut hasher = Sha256::new();
+        let length = io::copy(&mut reader.by_ref().take(expected as u64), &mut hasher)? as usize;
+
+        if length == 0 {
+            return Ok(chunks);
+        }
+        chunks.push(Chunk { offset, length, hash: hasher.finalize().into(), data: Vec::new() });
+        offset += length;
+        if length < expected {
+            return Ok(chunks);
+        }
+    }
+
+    let mut hasher = Sha256::new();
+    let length = io::copy(&mut reader, &mut hasher)? as usize;
+    if length > 0 {
+        chunks.push(Chunk { offset, length, hash: hasher.finalize().into(), data: Vec::new() });
+    }
+
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rs_merkle::MerkleTree;
+    use rs_merkle::algorithms::Sha256 as MerkleHasher;
+
+    /// Deterministic bytes from a xorshift generator.
+    fn pseudo_random(length: usize, seed: u64) -> Vec<u8> {
+        let mut state: u64 = seed;
+
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    /// Reader handing out a few bytes at a time, to move the buffer edges.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.step = self.step % 7 + 1;
+            let length = self.step.min(buffer.len()).min(self.data.len());
+            buffer[..length].copy_from_slice(&self.data[..length]);
+            self.data = &self.data[length..];
+
+            Ok(length)
+        }
+    }
+
+    /// Boundaries as the first, whole-buffer implementation found them.
+    fn original_boundaries(buffer: &[u8]) -> Vec<usize> {
+        let mut hashes: Vec<u32> = vec![0];
+        if buffer.len() >= CDC_WINDOW_SIZE {
+            let mut hash: u32 = buffer[..CDC_WINDOW_SIZE].iter().fold(0u32, |hash, &byte| hash.wrapping_add(byte as u32));
+            hashes = vec![hash];
+            for i in CDC_WINDOW_SIZE..buffer.len() {
+                hash = hash.wrapping_add(buffer[i] as u32).wrapping_sub(buffer[i - CDC_WINDOW_SIZE] as u32);
+                hashes.push(hash);
+            }
+        }
+        let mut boundaries: Vec<usize> = vec![0];
+        let mut current_chunk_size: usize = 0;
+
+        for (i, hash) in hashes.iter().enumerate() {
+            current_chunk_size += 1;
+            if current_chunk_size >= CDC_AVERAGE_CHUNK_SIZE / 4
+                && ((hash & CDC_MASK) == 0 || current_chunk_size >= CDC_AVERAGE_CHUNK_SIZE * 4)
+                && i + CDC_WINDOW_SIZE < buffer.len()
+            {
+                boundaries.push(i + CDC_WINDOW_SIZE);
+                current_chunk_size = 0;
+            }
+        }
+        if boundaries.last() != Some(&buffer.len()) {
+            boundaries.push(buffer.len());
+        }
+
+        boundaries
+    }
+
+    fn chunks<R: Read>(reader: R, version: ChunkerVersion) -> Vec<Chunk> {
+        Chunker::new(reader, version).collect::<io::Result<Vec<Chunk>>>().unwrap()
+    }
+
+    fn boundaries(chunks: &[Chunk]) -> Vec<usize> {
+        std::iter::once(0).chain(chunks.iter().map(|chunk| chunk.offset + chunk.length)).collect()
+    }
+
+    fn root(leaves: &[[u8; 32]]) -> Option<[u8; 32]> {
+        MerkleTree::<MerkleHasher>::from_leaves(leaves).root()
+    }
+
+    #[test]
+    fn additive_chunker_reproduces_the_original_roots() {
+        let text: Vec<u8> = b"glacier keeps files as they were written. ".repeat(5000);
+        let inputs: Vec<Vec<u8>> = vec![
+            pseudo_random(300_000, 1),
+            pseudo_random(CDC_MAX_CHUNK_SIZE * 3 + 17, 2),
+            text,
+            vec![0u8; 70_000],
+            pseudo_random(CDC_WINDOW_SIZE, 3),
+            pseudo_random(10, 4),
+        ];
+
+        for input in inputs {
+            let expected: Vec<usize> = original_boundaries(&input);
+            let expected_leaves: Vec<[u8; 32]> = expected
+                .windows(2)
+                .map(|range| Sha256::digest(&input[range[0]..range[1]]).into())
+                .collect();
+            let chunks: Vec<Chunk> = chunks(&input[..], ChunkerVersion::Additive);
+            let leaves: Vec<[u8; 32]> = chunks.iter().map(|chunk| chunk.hash).collect();
+
+            assert_eq!(boundaries(&chunks), expected, "input of {} bytes", input.len());
+            assert_eq!(root(&leaves), root(&expected_leaves));
+        }
+    }
+
+    /// Recorded Gear signatures are only verifiable while the table, masks
+    /// and normalization cut files where they did when recorded.
+    #[test]
+    fn gear_boundaries_are_stable() {
+        let input: Vec<u8> = pseudo_random(65_536, 0x5eed);
+
+        assert_eq!(
+            boundaries(&chunks(&input[..], ChunkerVersion::Gear)),
+            vec![0, 4457, 5845, 9948, 14244, 15847, 20295, 21403, 25605, 30604, 35835, 40779, 46129, 50486, 54527, 60280, 65536]
+        );
+    }
+
+    #[test]
+    fn reads_recorded_chunker_versions() {
+        assert_eq!(ChunkerVersion::from_version(CDC_CHUNKER_VERSION_ADDITIVE), Some(ChunkerVersion::Additive));
+        assert_eq!(ChunkerVersion::from_version(CDC_CHUNKER_VERSION_GEAR), Some(ChunkerVersion::Gear));
+        assert_eq!(ChunkerVersion::from_version(0), None);
+    }
+
+    #[test]
+    fn boundaries_do_not_depend_on_how_the_source_is_read() {
+        let input: Vec<u8> = pseudo_random(200_000, 5);
+
+        for version in [ChunkerVersion::Additive, ChunkerVersion::Gear] {
+            let whole: Vec<Chunk> = chunks(&input[..], version);
+            let trickled: Vec<Chunk> = chunks(Trickle { data: &input, step: 0 }, version);
+
+            assert_eq!(boundaries(&whole), boundaries(&trickled));
+            assert!(whole.iter().zip(&trickled).all(|(a, b)| a.hash == b.hash));
+        }
+    }
+
+    #[test]
+    fn gear_boundaries_resynchronize_after_an_insertion() {
+        let original: Vec<u8> = pseudo_random(400_000, 6);
+        let mut edited: Vec<u8> = original.clone();
+        edited.splice(100_000..100_000, pseudo_random(100, 7));
+        let original_chunks: Vec<Chunk> = chunks(&original[..], ChunkerVersion::Gear);
+        let edited_leaves: Vec<[u8; 32]> = chunks(&edited[..], ChunkerVersion::Gear).iter().map(|chunk| chunk.hash).collect();
+        let changed: usize = original_chunks.iter().filter(|chunk| !edited_leaves.contains(&chunk.hash)).count();
+
+        assert!(changed <= 2, "{} of {} chunks changed", changed, original_chunks.len());
+    }
+
+    #[test]
+    fn chunk_data_matches_the_source() {
+        let input: Vec<u8> = pseudo_random(100_000, 8);
+
+        for chunk in Chunker::with_data(&input[..], ChunkerVersion::Gear) {
+            let chunk: Chunk = chunk.unwrap();
+            assert!(chunk.length <= CDC_MAX_CHUNK_SIZE);
+            assert_eq!(chunk.data, &input[chunk.offset..chunk.offset + chunk.length]);
+        }
+    }
+}
@@ -1,17 +1,33 @@
-use std::io::{self, Read, Write};
-use log::info;
-use std::fs::File as FsFile;
+use glob::{MatchOptions, Pattern};
+use std::io;
+use log::{error, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::utils::constants::AGENT_TEMPORARY_SUFFIXES;
 
-pub struct File {
-    pub path: String
+/// How patterns given by a person match relative paths: `*` stays within
+/// a directory and `**` spans any number of them.
+pub const PATTERN_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    /// Symbolic links are never followed, so a link cannot pull files from
+    /// outside the storage directory into the verification.
+    Symlink,
+    /// FIFOs, sockets and device nodes have no stable content to sign.
+    Special,
+    Unreadable(String),
 }
 
-impl File {
-    pub fn new(path: &str) -> Self {
-        Self { 
-            path: path.to_string()
-        }
-    }
+#[derive(Debug, Clone)]
+pub struct StorageEntry {
+    pub relative_path: String,
+    pub kind: EntryKind,
 }
 
 pub struct FileHandler {
@@ -24,33 +40,212 @@ impl FileHandler {
         Self { storage_dir: storage_dir.to_string() }
     }
 
-    pub fn get_storage_dir(&self) -> &str {
-        &self.storage_dir
-    }
-
     pub fn prepare_file_path(&self, file_name: &str) -> String {
         format!("{}/{}", self.storage_dir, file_name)
     }
 
-    pub fn create_file(&self, file_name: &str) -> File {
-        let path: String = self.prepare_file_path(file_name);
+    /// Walks the storage directory recursively and returns every entry
+    /// keyed by its `/`-separated path relative to the storage root, sorted
+    /// by that path. Names that are not valid UTF-8 cannot be keyed without
+    /// colliding with another name, so they are skipped with an error, and
+    /// the temporary files the agent writes next to restored or repaired
+    /// files are left out.
+    pub fn walk_storage(&self) -> io::Result<Vec<StorageEntry>> {
+        let root: PathBuf = PathBuf::from(&self.storage_dir);
+        let mut entries: Vec<StorageEntry> = Vec::new();
+        let mut directories: Vec<PathBuf> = vec![PathBuf::new()];
+
+        while let Some(directory) = directories.pop() {
+            let read_dir: fs::ReadDir = match fs::read_dir(root.join(&directory)) {
+                Ok(read_dir) => read_dir,
+                Err(e) if directory.as_os_str().is_empty() => return Err(e),
+                Err(e) => {
+                    warn!("Failed to read directory '{}': {}", directory.display(), e);
+                    entries.push(StorageEntry {
+                        relative_path: Self::relative_path(&directory).unwrap_or_default(),
+                        kind: EntryKind::Unreadable(e.to_string()),
+                    });
+                    continue;
+                }
+            };
+
+            for entry in read_dir {
+                let entry: fs::DirEntry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        warn!("Failed to read an entry of '{}': {}", directory.display(), e);
+                        entries.push(StorageEntry {
+                            relative_path: Self::relative_path(&directory).unwrap_or_default(),
+                            kind: EntryKind::Unreadable(e.to_string()),
+                        });
+                        continue;
+                    }
+                };
+                let relative: PathBuf = directory.join(entry.file_name());
+                let relative_path: String = match Self::relative_path(&relative) {
+                    Some(relative_path) => relative_path,
+                    None => {
+                        error!("Skipping '{}': the name is not valid UTF-8", root.join(&relative).display());
+                        continue;
+                    }
+                };
+                if Self::is_temporary(&relative_path) {
+                    continue;
+                }
+                let kind: EntryKind = match entry.file_type() {
+                    Ok(file_type) if file_type.is_dir() => {
+                        directories.push(relative);
+                        continue;
+                    }
+                    Ok(file_type) if file_type.is_file() => EntryKind::File,
+                    Ok(file_type) if file_type.is_symlink() => EntryKind::Symlink,
+                    Ok(_) => EntryKind::Special,
+                    Err(e) => EntryKind::Unreadable(e.to_string()),
+                };
+
+                entries.push(StorageEntry { relative_path, kind });
+            }
+        }
+        entries.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+
+        Ok(entries)
+    }
+
+    /// Relative paths of the regular files matching any of `patterns`. A
+    /// pattern is a path or glob relative to the storage directory, or an
+    /// absolute one inside it; `*` stays within a directory and `**` spans
+    /// any number of them.
+    pub fn select_files(&self, patterns: &[String]) -> Result<Vec<String>, String> {
+        let compiled: Vec<Pattern> = self.compile_patterns(patterns)?;
+        let entries: Vec<StorageEntry> = self.walk_storage()
+            .map_err(|e| format!("Failed to read storage directory: {}", e))?;
+        let mut matched: Vec<bool> = vec![false; compiled.len()];
+        let mut file_names: Vec<String> = Vec::new();
+
+        for entry in entries.into_iter().filter(|entry| entry.kind == EntryKind::File) {
+            let mut selected: bool = false;
+            for (index, pattern) in compiled.iter().enumerate() {
+                if pattern.matches_with(&entry.relative_path, PATTERN_OPTIONS) {
+                    matched[index] = true;
+                    selected = true;
+                }
+            }
+            if selected {
+                file_names.push(entry.relative_path);
+            }
+        }
+        for (pattern, matched) in patterns.iter().zip(matched) {
+            if !matched {
+                warn!("No file in {} matches '{}'", self.storage_dir, pattern);
+            }
+        }
+
+        Ok(file_names)
+    }
+
+    /// Compiles patterns relative to the storage directory, or absolute
+    /// ones inside it, to match relative paths with `PATTERN_OPTIONS`.
+    pub fn compile_patterns(&self, patterns: &[String]) -> Result<Vec<Pattern>, String> {
+        let root: String = format!("{}/", self.storage_dir.trim_end_matches('/'));
+
+        patterns
+            .iter()
+            .map(|pattern| {
+                let relative: &str = pattern.strip_prefix(&root).unwrap_or(pattern).trim_start_matches("./");
+                Pattern::new(relative).map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))
+            })
+            .collect()
+    }
+
+    /// The relative path of `path` if it lies inside the storage directory
+    /// and `walk_storage` would list it.
+    pub fn relative_name(&self, path: &Path) -> Option<String> {
+        let relative: &Path = path.strip_prefix(&self.storage_dir).ok()?;
+        match Self::relative_path(relative) {
+            Some(name) if name.is_empty() || Self::is_temporary(&name) => None,
+            Some(name) => Some(name),
+            None => {
+                error!("Skipping '{}': the name is not valid UTF-8", path.display());
+                None
+            }
+        }
+    }
+
+    /// Whether the last component of `name` is a hidden file ending in one
+    /// of the suffixes the agent uses for its own temporary files.
+    fn is_temporary(name: &str) -> bool {
+        let last: &str = name.rsplit('/').next().unwrap_or(name);
+        last.starts_with('.') && AGENT_TEMPORARY_SUFFIXES.iter().any(|suffix| last.ends_with(suffix))
+    }
 
-        File::new(&path)
+    fn relative_path(path: &Path) -> Option<String> {
+        path.components()
+            .map(|component| component.as_os_str().to_str())
+            .collect::<Option<Vec<&str>>>()
+            .map(|components| components.join("/"))
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::constants::RESTORE_TEMPORARY_SUFFIX;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
 
-    pub fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
-        let mut file: FsFile = FsFile::open(path)?;
-        let mut buffer: Vec<u8> = Vec::new();
+    /// Storage directory removed once the test is over.
+    struct TestStorage(PathBuf);
 
-        file.read_to_end(&mut buffer)?;
+    impl TestStorage {
+        fn new(name: &str) -> Self {
+            let path: PathBuf = std::env::temp_dir().join(format!("glacier-file-handler-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+
+            Self(path)
+        }
 
-        Ok(buffer)
+        fn handler(&self) -> FileHandler {
+            FileHandler::new(&self.0.to_string_lossy())
+        }
     }
 
-    pub fn write_file(&self, path: &str, data: &[u8]) -> io::Result<()> {
-        let mut file: FsFile = FsFile::create(path)?;
-        file.write_all(data)?;
+    impl Drop for TestStorage {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn skips_names_that_are_not_utf8() {
+        let storage: TestStorage = TestStorage::new("utf8");
+        let invalid: &OsStr = OsStr::from_bytes(b"caf\xe9.txt");
+        fs::write(storage.0.join("caf\u{fffd}.txt"), b"a").unwrap();
+        fs::write(storage.0.join(invalid), b"b").unwrap();
+        fs::create_dir(storage.0.join(OsStr::from_bytes(b"dir\xff"))).unwrap();
+        fs::write(storage.0.join(OsStr::from_bytes(b"dir\xff")).join("inner.txt"), b"c").unwrap();
+        let handler: FileHandler = storage.handler();
+
+        let names: Vec<String> = handler.walk_storage().unwrap().into_iter().map(|entry| entry.relative_path).collect();
+
+        assert_eq!(names, vec!["caf\u{fffd}.txt".to_string()]);
+        assert_eq!(handler.relative_name(&storage.0.join(invalid)), None);
+        assert_eq!(handler.relative_name(&storage.0.join("a/b.txt")), Some("a/b.txt".to_string()));
+        assert_eq!(handler.relative_name(&storage.0), None);
+    }
+
+    #[test]
+    fn leaves_out_agent_temporary_files() {
+        let storage: TestStorage = TestStorage::new("temporary");
+        let temporary: String = format!(".report.pdf{}", RESTORE_TEMPORARY_SUFFIX);
+        fs::write(storage.0.join("report.pdf"), b"a").unwrap();
+        fs::write(storage.0.join(&temporary), b"a").unwrap();
+        fs::write(storage.0.join(format!("visible{}", RESTORE_TEMPORARY_SUFFIX)), b"b").unwrap();
+        let handler: FileHandler = storage.handler();
+
+        let names: Vec<String> = handler.walk_storage().unwrap().into_iter().map(|entry| entry.relative_path).collect();
 
-        Ok(())
+        assert_eq!(names, vec!["report.pdf".to_string(), format!("visible{}", RESTORE_TEMPORARY_SUFFIX)]);
+        assert_eq!(handler.relative_name(&storage.0.join(&temporary)), None);
     }
 }
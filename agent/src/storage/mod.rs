@@ -1,2 +1,10 @@
+pub mod backup_handler;
+pub mod chunk_diff;
+pub mod chunk_store;
+pub mod chunker;
 pub mod file_handler;
+pub mod parity_handler;
+pub mod repair_handler;
 pub mod signature_handler;
+pub mod signature_store;
+pub mod stores;
@@ -0,0 +1,393 @@
+use chrono::Utc;
+use log::info;
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::storage::repair_handler::ChunkSource;
+use crate::storage::signature_handler::Signature;
+use crate::utils::constants::{
+    PARITY_DATA_SHARDS,
+    PARITY_PARITY_SHARDS
+};
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ParityGroup {
+    pub first_chunk: usize,
+    pub chunk_count: usize,
+    pub shard_size: usize,
+    pub offset: u64,
+    pub parity_hashes: Vec<String>,
+}
+
+/// Erasure-coding layout of one file. Every group of up to
+/// `PARITY_DATA_SHARDS` consecutive chunks, zero-padded to its largest
+/// chunk, carries `parity_shards` Reed-Solomon shards, so any
+/// `parity_shards` damaged chunks of a group can be rebuilt.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ParityManifest {
+    pub file_name: String,
+    pub signature: String,
+    pub parity_shards: usize,
+    pub groups: Vec<ParityGroup>,
+    pub created_at: String,
+}
+
+pub struct ParityHandler {
+    parity_dir: String,
+}
+
+impl ParityHandler {
+    pub fn new(parity_dir: &str) -> Self {
+        info!("Parity directory set: {}", parity_dir);
+        Self {
+            parity_dir: parity_dir.to_string(),
+        }
+    }
+
+    fn parity_stem(&self, file_name: &str) -> PathBuf {
+        let name: String = hex::encode(Sha256::digest(file_name.as_bytes()));
+
+        PathBuf::from(&self.parity_dir).join(&name[..2]).join(name)
+    }
+
+    fn parity_path(&self, file_name: &str) -> PathBuf {
+        self.parity_stem(file_name).with_extension("parity")
+    }
+
+    fn manifest_path(&self, file_name: &str) -> PathBuf {
+        self.parity_stem(file_name).with_extension("parity.json")
+    }
+
+    pub fn load_manifest(&self, file_name: &str) -> Result<Option<ParityManifest>, String> {
+        let file: File = match File::open(self.manifest_path(file_name)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to open parity manifest: {}", e)),
+        };
+
+        serde_json::from_reader(BufReader::new(file))
+            .map(Some)
+            .map_err(|e| format!("Failed to parse parity manifest: {}", e))
+    }
+
+    /// Computes parity shards for `file_path`, reading one group of chunks
+    /// at a time. Chunks that no longer match `stored` abort the run, so
+    /// parity is only ever derived from verified content.
+    pub fn generate(&self, file_name: &str, file_path: &str, stored: &Signature) -> Result<ParityManifest, String> {
+        let parity_path: PathBuf = self.parity_path(file_name);
+        let temporary_path: PathBuf = parity_path.with_extension("parity.tmp");
+
+        if let Some(parent) = parity_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create parity directory: {}", e))?;
+        }
+        let groups: Vec<ParityGroup> = match self.write_parity(file_path, &temporary_path, stored) {
+            Ok(groups) => groups,
+            Err(e) => {
+                let _ = fs::remove_file(&temporary_path);
+                return Err(e);
+            }
+        };
+        fs::rename(&temporary_path, &parity_path)
+            .map_err(|e| format!("Failed to publish parity file: {}", e))?;
+
+        let manifest: ParityManifest = ParityManifest {
+            file_name: file_name.to_string(),
+            signature: stored.signature.clone(),
+            parity_shards: PARITY_PARITY_SHARDS,
+            groups,
+            created_at: Utc::now().to_rfc3339(),
+        };
+        let manifest_path: PathBuf = self.manifest_path(file_name);
+        let manifest_temporary_path: PathBuf = manifest_path.with_extension("json.tmp");
+        let contents: Vec<u8> = serde_json::to_vec_pretty(&manifest)
+            .map_err(|e| format!("Failed to serialize parity manifest: {}", e))?;
+        fs::write(&manifest_temporary_path, contents)
+            .and_then(|_| fs::rename(&manifest_temporary_path, &manifest_path))
+            .map_err(|e| format!("Failed to write parity manifest: {}", e))?;
+        info!("Generated parity for {} chunk groups of '{}'", manifest.groups.len(), file_name);
+
+        Ok(manifest)
+    }
+
+    fn write_parity(&self, file_path: &str, path: &Path, stored: &Signature) -> Result<Vec<ParityGroup>, String> {
+        let write_error = |e: io::Error| format!("Failed to write parity file: {}", e);
+        let mut reader: BufReader<File> = BufReader::new(
+            File::open(file_path).map_err(|e| format!("Failed to open file: {}", e))?
+        );
+        let mut writer: BufWriter<File> = BufWriter::new(File::create(path).map_err(write_error)?);
+        let mut groups: Vec<ParityGroup> = Vec::new();
+        let mut offset: u64 = 0;
+
+        for first_chunk in (0..stored.leaves.len()).step_by(PARITY_DATA_SHARDS) {
+            let chunk_count: usize = std::cmp::min(PARITY_DATA_SHARDS, stored.leaves.len() - first_chunk);
+            let mut shards: Vec<Vec<u8>> = Vec::with_capacity(chunk_count);
+
+            for index in first_chunk..first_chunk + chunk_count {
+                let (start, end) = chunk_range(stored, index)?;
+                let mut data: Vec<u8> = vec![0u8; end - start];
+                reader.read_exact(&mut data).map_err(|e| format!("Failed to read file: {}", e))?;
+                if hex::encode(Sha256::digest(&data)) != stored.leaves[index] {
+                    return Err(format!("Chunk {} no longer matches the stored signature", index));
+                }
+                shards.push(data);
+            }
+
+            let shard_size: usize = shards.iter().map(Vec::len).max().unwrap_or(0);
+            for shard in shards.iter_mut() {
+                shard.resize(shard_size, 0);
+            }
+            let mut parity: Vec<Vec<u8>> = vec![vec![0u8; shard_size]; PARITY_PARITY_SHARDS];
+            ReedSolomon::new(chunk_count, PARITY_PARITY_SHARDS)
+                .and_then(|codec| codec.encode_sep(&shards, &mut parity))
+                .map_err(|e| format!("Failed to compute parity: {:?}", e))?;
+
+            for shard in &parity {
+                writer.write_all(shard).map_err(write_error)?;
+            }
+            groups.push(ParityGroup {
+                first_chunk,
+                chunk_count,
+                shard_size,
+                offset,
+                parity_hashes: parity.iter().map(|shard| hex::encode(Sha256::digest(shard))).collect(),
+            });
+            offset += (shard_size * PARITY_PARITY_SHARDS) as u64;
+        }
+        writer.into_inner()
+            .map_err(|e| write_error(e.into_error()))?
+            .sync_all()
+            .map_err(write_error)?;
+
+        Ok(groups)
+    }
+
+    fn read_parity(&self, manifest: &ParityManifest, group: &ParityGroup) -> io::Result<Vec<Option<Vec<u8>>>> {
+        let mut file: File = File::open(self.parity_path(&manifest.file_name))?;
+        let mut shards: Vec<Option<Vec<u8>>> = Vec::with_capacity(manifest.parity_shards);
+
+        file.seek(SeekFrom::Start(group.offset))?;
+        for expected in &group.parity_hashes {
+            let mut shard: Vec<u8> = vec![0u8; group.shard_size];
+            file.read_exact(&mut shard)?;
+            let intact: bool = hex::encode(Sha256::digest(&shard)) == *expected;
+            shards.push(if intact { Some(shard) } else { None });
+        }
+
+        Ok(shards)
+    }
+}
+
+/// Rebuilds damaged chunks from the intact chunks of the same group in the
+/// file itself and the group's parity shards, without any second copy.
+pub struct ParityChunkSource<'a> {
+    pub parity_handler: &'a ParityHandler,
+    pub manifest: ParityManifest,
+    pub file_path: String,
+    pub stored: &'a Signature,
+}
+
+impl ChunkSource for ParityChunkSource<'_> {
+    fn name(&self) -> String {
+        "parity".to_string()
+    }
+
+    fn read_chunk(&self, index: usize) -> Result<Vec<u8>, String> {
+        let group: &ParityGroup = self.manifest.groups
+            .iter()
+            .find(|group| index >= group.first_chunk && index < group.first_chunk + group.chunk_count)
+            .ok_or_else(|| format!("Chunk {} is not covered by parity", index))?;
+        let mut file: File = File::open(&self.file_path)
+            .map_err(|e| format!("Failed to open file: {}", e))?;
+        let mut shards: Vec<Option<Vec<u8>>> = Vec::with_capacity(group.chunk_count + self.manifest.parity_shards);
+
+        for chunk in group.first_chunk..group.first_chunk + group.chunk_count {
+            let (start, end) = chunk_range(self.stored, chunk)?;
+            let expected: &String = self.stored.leaves
+                .get(chunk)
+                .ok_or_else(|| format!("Chunk {} is not in the signature", chunk))?;
+            let mut data: Vec<u8> = vec![0u8; end - start];
+            let read = file.seek(SeekFrom::Start(start as u64)).and_then(|_| file.read_exact(&mut data));
+            let intact: bool = read.is_ok() && hex::encode(Sha256::digest(&data)) == *expected;
+
+            if intact {
+                data.resize(group.shard_size, 0);
+                shards.push(Some(data));
+            } else {
+                shards.push(None);
+            }
+        }
+        shards.extend(
+            self.parity_handler
+                .read_parity(&self.manifest, group)
+                .map_err(|e| format!("Failed to read parity file: {}", e))?
+        );
+
+        ReedSolomon::new(group.chunk_count, self.manifest.parity_shards)
+            .and_then(|codec| codec.reconstruct_data(&mut shards))
+            .map_err(|e| format!("Failed to rebuild chunk {} from parity: {:?}", index, e))?;
+
+        let mut data: Vec<u8> = shards[index - group.first_chunk]
+            .take()
+            .ok_or_else(|| format!("Chunk {} could not be rebuilt", index))?;
+        let (start, end) = chunk_range(self.stored, index)?;
+        data.truncate(end - start);
+
+        Ok(data)
+    }
+}
+
+/// The byte range the signature records for chunk `index`.
+fn chunk_range(stored: &Signature, index: usize) -> Result<(usize, usize), String> {
+    match (stored.chunk_positions.get(index), stored.chunk_positions.get(index + 1)) {
+        (Some(&start), Some(&end)) if start <= end => Ok((start, end)),
+        _ => Err(format!("Chunk {} has no valid position in the signature", index)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parity directory and source file removed once the test is over.
+    struct TestFiles(PathBuf);
+
+    impl TestFiles {
+        fn new(name: &str) -> Self {
+            let path: PathBuf = std::env::temp_dir().join(format!("glacier-parity-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+
+            Self(path)
+        }
+
+        fn file_path(&self) -> String {
+            self.0.join("data.bin").to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for TestFiles {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Writes 20 chunks of different lengths, two parity groups, and the
+    /// signature recorded for them.
+    fn setup(files: &TestFiles) -> (ParityHandler, ParityManifest, Signature, Vec<Vec<u8>>) {
+        let chunks: Vec<Vec<u8>> = (0..20usize)
+            .map(|index| (0..100 + index * 37).map(|byte| (byte * 31 + index * 7) as u8).collect())
+            .collect();
+        let mut chunk_positions: Vec<usize> = vec![0];
+        for chunk in &chunks {
+            chunk_positions.push(chunk_positions.last().unwrap() + chunk.len());
+        }
+        fs::write(files.file_path(), chunks.concat()).unwrap();
+        let stored: Signature = Signature {
+            file_name: "data.bin".to_string(),
+            version: 1,
+            created_at: None,
+            author: None,
+            reason: None,
+            signature: String::new(),
+            leaves: chunks.iter().map(|chunk| hex::encode(Sha256::digest(chunk))).collect(),
+            chunk_positions,
+            chunker_version: 2,
+            signing_key_id: None,
+            record_signature: None,
+        };
+        let parity_handler: ParityHandler = ParityHandler::new(&files.0.join("parity").to_string_lossy());
+        let manifest: ParityManifest = parity_handler.generate("data.bin", &files.file_path(), &stored).unwrap();
+
+        (parity_handler, manifest, stored, chunks)
+    }
+
+    fn corrupt(files: &TestFiles, stored: &Signature, indices: &[usize]) {
+        let mut contents: Vec<u8> = fs::read(files.file_path()).unwrap();
+        for &index in indices {
+            contents[stored.chunk_positions[index]] ^= 0xff;
+        }
+        fs::write(files.file_path(), contents).unwrap();
+    }
+
+    fn source<'a>(files: &TestFiles, parity_handler: &'a ParityHandler, manifest: &ParityManifest, stored: &'a Signature) -> ParityChunkSource<'a> {
+        ParityChunkSource {
+            parity_handler,
+            manifest: manifest.clone(),
+            file_path: files.file_path(),
+            stored,
+        }
+    }
+
+    #[test]
+    fn groups_chunks_with_their_parity() {
+        let files: TestFiles = TestFiles::new("groups");
+        let (_, manifest, _, chunks) = setup(&files);
+
+        assert_eq!(manifest.groups.len(), 2);
+        assert_eq!((manifest.groups[0].first_chunk, manifest.groups[0].chunk_count), (0, PARITY_DATA_SHARDS));
+        assert_eq!((manifest.groups[1].first_chunk, manifest.groups[1].chunk_count), (PARITY_DATA_SHARDS, 20 - PARITY_DATA_SHARDS));
+        assert_eq!(manifest.groups[0].shard_size, chunks[PARITY_DATA_SHARDS - 1].len());
+        assert_eq!(manifest.groups[1].offset, (manifest.groups[0].shard_size * PARITY_PARITY_SHARDS) as u64);
+    }
+
+    #[test]
+    fn rebuilds_as_many_damaged_chunks_as_there_are_parity_shards() {
+        let files: TestFiles = TestFiles::new("rebuild");
+        let (parity_handler, manifest, stored, chunks) = setup(&files);
+        let damaged: Vec<usize> = vec![0, 5, 9, PARITY_DATA_SHARDS - 1, 17];
+        corrupt(&files, &stored, &damaged);
+        let source: ParityChunkSource = source(&files, &parity_handler, &manifest, &stored);
+
+        for index in damaged {
+            assert_eq!(source.read_chunk(index).unwrap(), chunks[index], "chunk {}", index);
+        }
+    }
+
+    #[test]
+    fn gives_up_when_more_chunks_are_damaged_than_parity_covers() {
+        let files: TestFiles = TestFiles::new("too-many");
+        let (parity_handler, manifest, stored, _) = setup(&files);
+        corrupt(&files, &stored, &[1, 2, 3, 4, 6]);
+
+        assert!(source(&files, &parity_handler, &manifest, &stored).read_chunk(1).is_err());
+    }
+
+    #[test]
+    fn skips_damaged_parity_shards() {
+        let files: TestFiles = TestFiles::new("damaged-parity");
+        let (parity_handler, manifest, stored, chunks) = setup(&files);
+        corrupt(&files, &stored, &[2, 7, 11]);
+        let parity_path: PathBuf = parity_handler.parity_path("data.bin");
+        let mut parity: Vec<u8> = fs::read(&parity_path).unwrap();
+        parity[0] ^= 0xff;
+        fs::write(&parity_path, parity).unwrap();
+        let source: ParityChunkSource = source(&files, &parity_handler, &manifest, &stored);
+
+        assert_eq!(source.read_chunk(7).unwrap(), chunks[7]);
+        corrupt(&files, &stored, &[12]);
+        assert!(source.read_chunk(7).is_err());
+    }
+
+    #[test]
+    fn refuses_parity_for_content_that_changed() {
+        let files: TestFiles = TestFiles::new("changed");
+        let (parity_handler, _, stored, _) = setup(&files);
+        corrupt(&files, &stored, &[3]);
+
+        assert!(parity_handler.generate("data.bin", &files.file_path(), &stored).is_err());
+    }
+
+    #[test]
+    fn refuses_chunks_without_a_position() {
+        let files: TestFiles = TestFiles::new("positions");
+        let (parity_handler, manifest, mut stored, _) = setup(&files);
+        corrupt(&files, &stored, &[18]);
+        stored.chunk_positions.truncate(19);
+
+        assert!(source(&files, &parity_handler, &manifest, &stored).read_chunk(18).is_err());
+        assert!(parity_handler.generate("data.bin", &files.file_path(), &stored).is_err());
+    }
+}
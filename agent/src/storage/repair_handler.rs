@@ -0,0 +1,468 @@
+use log::{info, warn};
+use rs_merkle::MerkleTree;
+use rs_merkle::algorithms::Sha256 as MerkleHasher;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::storage::backup_handler::{BackupHandler, VaultManifest};
+use crate::storage::signature_handler::Signature;
+use crate::utils::constants::{REPAIR_JOURNAL_MAGIC, REPAIR_JOURNAL_SUFFIX, REPAIR_JOURNAL_VERSION};
+
+/// Known-good origin for the content of individual chunks of a file.
+pub trait ChunkSource {
+    fn name(&self) -> String;
+    fn read_chunk(&self, index: usize) -> Result<Vec<u8>, String>;
+}
+
+pub struct VaultChunkSource<'a> {
+    pub backup_handler: &'a BackupHandler,
+    pub manifest: VaultManifest,
+}
+
+impl ChunkSource for VaultChunkSource<'_> {
+    fn name(&self) -> String {
+        "vault".to_string()
+    }
+
+    fn read_chunk(&self, index: usize) -> Result<Vec<u8>, String> {
+        self.backup_handler.read_chunk(&self.manifest, index).map_err(|e| e.to_string())
+    }
+}
+
+/// Copy of the file kept under the same relative path in another directory.
+pub struct ReplicaChunkSource {
+    pub path: String,
+    pub chunk_positions: Vec<usize>,
+}
+
+impl ChunkSource for ReplicaChunkSource {
+    fn name(&self) -> String {
+        format!("replica {}", self.path)
+    }
+
+    fn read_chunk(&self, index: usize) -> Result<Vec<u8>, String> {
+        let file: File = File::open(&self.path)
+            .map_err(|e| format!("Failed to open replica: {}", e))?;
+
+        read_range(file, &self.chunk_positions, index)
+            .map_err(|e| format!("Failed to read replica chunk {}: {}", index, e))
+    }
+}
+
+fn read_range<R: Read + Seek>(mut reader: R, chunk_positions: &[usize], index: usize) -> io::Result<Vec<u8>> {
+    let (start, end) = match (chunk_positions.get(index), chunk_positions.get(index + 1)) {
+        (Some(&start), Some(&end)) if start <= end => (start, end),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk is not in the signature")),
+    };
+    let mut data: Vec<u8> = vec![0u8; end - start];
+
+    reader.seek(SeekFrom::Start(start as u64))?;
+    reader.read_exact(&mut data)?;
+
+    Ok(data)
+}
+
+/// Redo log of the chunk writes one repair makes, streamed to disk as the
+/// chunks are fetched and synced before the file is touched, so an
+/// interrupted repair can be finished. It holds the length of the file
+/// once repaired, each write as offset, size and data, then the number of
+/// writes and a digest of everything before it, which marks it complete.
+struct JournalWriter {
+    file: BufWriter<File>,
+    hasher: Sha256,
+    count: u32,
+}
+
+impl JournalWriter {
+    fn create(journal_path: &Path, length: u64) -> io::Result<Self> {
+        let mut journal: Self = Self {
+            file: BufWriter::new(File::create(journal_path)?),
+            hasher: Sha256::new(),
+            count: 0,
+        };
+        journal.write(REPAIR_JOURNAL_MAGIC)?;
+        journal.write(&[REPAIR_JOURNAL_VERSION])?;
+        journal.write(&length.to_le_bytes())?;
+
+        Ok(journal)
+    }
+
+    fn append(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.write(&offset.to_le_bytes())?;
+        self.write(&(data.len() as u32).to_le_bytes())?;
+        self.write(data)?;
+        self.count += 1;
+
+        Ok(())
+    }
+
+    /// Seals the journal and makes it durable. Tells how many writes it holds.
+    fn finish(mut self, journal_path: &Path) -> io::Result<u32> {
+        let count: u32 = self.count;
+        self.write(&count.to_le_bytes())?;
+        let digest = self.hasher.finalize_reset();
+        self.file.write_all(&digest)?;
+        self.file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        RepairHandler::sync_parent(journal_path)?;
+
+        Ok(count)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.hasher.update(bytes);
+        self.file.write_all(bytes)
+    }
+}
+
+/// A complete journal read back from disk one write at a time.
+struct JournalReader {
+    file: BufReader<File>,
+    length: u64,
+    count: u32,
+    /// Where the writes end and the count begins.
+    writes_end: u64,
+}
+
+impl JournalReader {
+    const HEADER_SIZE: u64 = REPAIR_JOURNAL_MAGIC.len() as u64 + 1 + 8;
+    const TRAILER_SIZE: u64 = 4 + 32;
+
+    /// Opens a journal, or `None` when it was not completely written.
+    fn open(journal_path: &Path) -> io::Result<Option<Self>> {
+        let mut file: BufReader<File> = BufReader::new(File::open(journal_path)?);
+        let size: u64 = file.get_ref().metadata()?.len();
+        let writes_end: u64 = match size.checked_sub(Self::HEADER_SIZE + Self::TRAILER_SIZE) {
+            Some(writes_size) => Self::HEADER_SIZE + writes_size,
+            None => return Ok(None),
+        };
+
+        let mut hasher: Sha256 = Sha256::new();
+        io::copy(&mut (&mut file).take(size - 32), &mut hasher)?;
+        let mut digest: [u8; 32] = [0u8; 32];
+        file.read_exact(&mut digest)?;
+        if hasher.finalize().as_slice() != digest {
+            return Ok(None);
+        }
+
+        file.seek(SeekFrom::Start(0))?;
+        let mut header: [u8; Self::HEADER_SIZE as usize] = [0u8; Self::HEADER_SIZE as usize];
+        file.read_exact(&mut header)?;
+        let (magic, rest) = header.split_at(REPAIR_JOURNAL_MAGIC.len());
+        if magic != REPAIR_JOURNAL_MAGIC || rest[0] != REPAIR_JOURNAL_VERSION {
+            return Ok(None);
+        }
+        let length: u64 = u64::from_le_bytes(rest[1..].try_into().unwrap_or_default());
+
+        file.seek(SeekFrom::Start(writes_end))?;
+        let count: u32 = u32::from_le_bytes(Self::read_array(&mut file)?);
+        file.seek(SeekFrom::Start(Self::HEADER_SIZE))?;
+
+        Ok(Some(Self { file, length, count, writes_end }))
+    }
+
+    /// Writes every chunk in place and cuts the file to its length. Safe to
+    /// apply again after a crash, since each write sets the same bytes.
+    fn apply(mut self, path: &Path) -> io::Result<()> {
+        let mut target: File = OpenOptions::new().write(true).open(path)?;
+        let mut position: u64 = Self::HEADER_SIZE;
+
+        for _ in 0..self.count {
+            let offset: u64 = u64::from_le_bytes(Self::read_array(&mut self.file)?);
+            let size: u32 = u32::from_le_bytes(Self::read_array(&mut self.file)?);
+            position += 8 + 4 + size as u64;
+            if position > self.writes_end {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "journal write runs past its end"));
+            }
+            target.seek(SeekFrom::Start(offset))?;
+            io::copy(&mut (&mut self.file).take(size as u64), &mut target)?;
+        }
+        if position != self.writes_end {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "journal count does not match its writes"));
+        }
+        target.set_len(self.length)?;
+
+        target.sync_all()
+    }
+
+    fn read_array<const N: usize>(file: &mut BufReader<File>) -> io::Result<[u8; N]> {
+        let mut bytes: [u8; N] = [0u8; N];
+        file.read_exact(&mut bytes)?;
+
+        Ok(bytes)
+    }
+}
+
+pub struct RepairHandler;
+
+impl RepairHandler {
+    /// Repairs `file_path` in place: every chunk that no longer matches its
+    /// stored leaf, including those in `corrupted_chunks`, is overwritten
+    /// with a copy from the first source that matches the leaf hash. The
+    /// writes go through a journal next to the file, so only the damaged
+    /// ranges need extra space and the file keeps its inode, owner and
+    /// mode. A journal left by an interrupted repair is finished first.
+    pub fn repair_file(file_path: &str, stored: &Signature, corrupted_chunks: &[usize], sources: &[&dyn ChunkSource]) -> Result<usize, String> {
+        let path: &Path = Path::new(file_path);
+        let file_name: String = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let journal_path: PathBuf = path.with_file_name(format!(".{}{}", file_name, REPAIR_JOURNAL_SUFFIX));
+
+        // A replayed journal may have fixed the chunks found corrupted
+        // before it, so every chunk is hashed again.
+        let corrupted_chunks: &[usize] = if Self::replay(path, &journal_path)? { &[] } else { corrupted_chunks };
+        let replaced: u32 = Self::plan(path, &journal_path, stored, corrupted_chunks, sources)
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&journal_path);
+            })?;
+
+        Self::apply(path, &journal_path)
+            .map_err(|e| format!("Failed to write repaired chunks, the journal will be replayed on the next repair: {}", e))?;
+        Self::remove_journal(&journal_path)?;
+
+        info!("Repaired '{}': replaced {} chunks", file_path, replaced);
+        Ok(replaced as usize)
+    }
+
+    fn apply(path: &Path, journal_path: &Path) -> io::Result<()> {
+        match JournalReader::open(journal_path)? {
+            Some(journal) => journal.apply(path),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "journal is incomplete")),
+        }
+    }
+
+    /// Finishes the repair an existing journal records. A journal that was
+    /// not completely written never reached the file and is dropped.
+    /// Tells whether the file was written to.
+    fn replay(path: &Path, journal_path: &Path) -> Result<bool, String> {
+        let journal: Option<JournalReader> = match JournalReader::open(journal_path) {
+            Ok(journal) => journal,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(format!("Failed to read repair journal: {}", e)),
+        };
+        let replayed: bool = match journal {
+            Some(journal) => {
+                warn!("Finishing the interrupted repair of '{}'", path.display());
+                journal.apply(path)
+                    .map_err(|e| format!("Failed to replay repair journal: {}", e))?;
+                true
+            }
+            None => {
+                warn!("Dropping the incomplete repair journal of '{}'", path.display());
+                false
+            }
+        };
+        Self::remove_journal(journal_path)?;
+
+        Ok(replayed)
+    }
+
+    /// Finds the chunks that need rewriting and journals a good copy of
+    /// each, holding one chunk in memory at a time. Tells how many chunks
+    /// the journal replaces.
+    fn plan(path: &Path, journal_path: &Path, stored: &Signature, corrupted_chunks: &[usize], sources: &[&dyn ChunkSource]) -> Result<u32, String> {
+        let journal_error = |e: io::Error| format!("Failed to write repair journal: {}", e);
+        let corrupted: HashSet<usize> = corrupted_chunks.iter().copied().collect();
+        let mut current: File = File::open(path)
+            .map_err(|e| format!("Failed to open file: {}", e))?;
+        let length: u64 = stored.chunk_positions.last().copied().unwrap_or(0) as u64;
+        let mut journal: JournalWriter = JournalWriter::create(journal_path, length).map_err(journal_error)?;
+        let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(stored.leaves.len());
+
+        for (index, expected) in stored.leaves.iter().enumerate() {
+            let offset: usize = *stored.chunk_positions.get(index)
+                .ok_or_else(|| format!("Chunk {} has no position in the signature", index))?;
+            let local: Option<Vec<u8>> = if corrupted.contains(&index) {
+                None
+            } else {
+                read_range(&mut current, &stored.chunk_positions, index)
+                    .ok()
+                    .filter(|data| hex::encode(Sha256::digest(data)) == *expected)
+            };
+            let data: Vec<u8> = match local {
+                Some(data) => data,
+                None => {
+                    let data: Vec<u8> = Self::fetch_chunk(index, expected, sources)?;
+                    journal.append(offset as u64, &data).map_err(journal_error)?;
+                    data
+                }
+            };
+
+            leaves.push(Sha256::digest(&data).into());
+        }
+
+        let root: String = MerkleTree::<MerkleHasher>::from_leaves(&leaves)
+            .root()
+            .map(hex::encode)
+            .unwrap_or_default();
+        if root != stored.signature {
+            return Err("Repaired file would not match the stored signature".to_string());
+        }
+
+        journal.finish(journal_path).map_err(journal_error)
+    }
+
+    fn remove_journal(journal_path: &Path) -> Result<(), String> {
+        fs::remove_file(journal_path)
+            .and_then(|_| Self::sync_parent(journal_path))
+            .map_err(|e| format!("Failed to remove repair journal: {}", e))
+    }
+
+    /// Makes the creation or removal of a file in its directory durable.
+    fn sync_parent(path: &Path) -> io::Result<()> {
+        match path.parent() {
+            Some(parent) => File::open(parent)?.sync_all(),
+            None => Ok(()),
+        }
+    }
+
+    fn fetch_chunk(index: usize, expected: &str, sources: &[&dyn ChunkSource]) -> Result<Vec<u8>, String> {
+        for source in sources {
+            match source.read_chunk(index) {
+                Ok(data) if hex::encode(Sha256::digest(&data)) == expected => return Ok(data),
+                Ok(_) => warn!("Chunk {} from {} does not match its leaf hash", index, source.name()),
+                Err(e) => warn!("Chunk {} unavailable from {}: {}", index, source.name(), e),
+            }
+        }
+
+        Err(format!("No source holds a good copy of chunk {}", index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    /// Storage and replica directories removed once the test is over.
+    struct TestFiles(PathBuf);
+
+    impl TestFiles {
+        fn new(name: &str) -> Self {
+            let path: PathBuf = std::env::temp_dir().join(format!("glacier-repair-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(path.join("replica")).unwrap();
+
+            Self(path)
+        }
+
+        fn file_path(&self) -> String {
+            self.0.join("data.bin").to_string_lossy().to_string()
+        }
+
+        fn journal_path(&self) -> PathBuf {
+            self.0.join(format!(".data.bin{}", REPAIR_JOURNAL_SUFFIX))
+        }
+    }
+
+    impl Drop for TestFiles {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Writes 8 chunks to the file and its replica, and the signature
+    /// recorded for them.
+    fn setup(files: &TestFiles) -> (Signature, ReplicaChunkSource, Vec<u8>) {
+        let chunks: Vec<Vec<u8>> = (0..8usize)
+            .map(|index| (0..200 + index * 53).map(|byte| (byte * 13 + index * 5) as u8).collect())
+            .collect();
+        let mut chunk_positions: Vec<usize> = vec![0];
+        for chunk in &chunks {
+            chunk_positions.push(chunk_positions.last().unwrap() + chunk.len());
+        }
+        let leaves: Vec<[u8; 32]> = chunks.iter().map(|chunk| Sha256::digest(chunk).into()).collect();
+        let contents: Vec<u8> = chunks.concat();
+        fs::write(files.file_path(), &contents).unwrap();
+        fs::write(files.0.join("replica/data.bin"), &contents).unwrap();
+        let stored: Signature = Signature {
+            file_name: "data.bin".to_string(),
+            version: 1,
+            created_at: None,
+            author: None,
+            reason: None,
+            signature: MerkleTree::<MerkleHasher>::from_leaves(&leaves).root().map(hex::encode).unwrap(),
+            leaves: leaves.iter().map(hex::encode).collect(),
+            chunk_positions: chunk_positions.clone(),
+            chunker_version: 2,
+            signing_key_id: None,
+            record_signature: None,
+        };
+        let replica: ReplicaChunkSource = ReplicaChunkSource {
+            path: files.0.join("replica/data.bin").to_string_lossy().to_string(),
+            chunk_positions,
+        };
+
+        (stored, replica, contents)
+    }
+
+    fn corrupt(files: &TestFiles, stored: &Signature, indices: &[usize]) {
+        let mut contents: Vec<u8> = fs::read(files.file_path()).unwrap();
+        for &index in indices {
+            contents[stored.chunk_positions[index]] ^= 0xff;
+        }
+        fs::write(files.file_path(), contents).unwrap();
+    }
+
+    #[test]
+    fn rewrites_damaged_chunks_in_place() {
+        let files: TestFiles = TestFiles::new("in-place");
+        let (stored, replica, contents) = setup(&files);
+        corrupt(&files, &stored, &[1, 6]);
+        fs::OpenOptions::new().append(true).open(files.file_path()).unwrap().write_all(b"trailing").unwrap();
+        let inode: u64 = fs::metadata(files.file_path()).unwrap().ino();
+
+        let replaced: usize = RepairHandler::repair_file(&files.file_path(), &stored, &[1], &[&replica]).unwrap();
+
+        assert_eq!(replaced, 2);
+        assert_eq!(fs::read(files.file_path()).unwrap(), contents);
+        assert_eq!(fs::metadata(files.file_path()).unwrap().ino(), inode);
+        assert!(!files.journal_path().exists());
+    }
+
+    #[test]
+    fn finishes_an_interrupted_repair_from_its_journal() {
+        let files: TestFiles = TestFiles::new("replay");
+        let (stored, _, contents) = setup(&files);
+        corrupt(&files, &stored, &[3]);
+        let (start, end) = (stored.chunk_positions[3], stored.chunk_positions[4]);
+        let mut journal: JournalWriter = JournalWriter::create(&files.journal_path(), contents.len() as u64).unwrap();
+        journal.append(start as u64, &contents[start..end]).unwrap();
+        journal.finish(&files.journal_path()).unwrap();
+
+        // No source is needed: the journal holds the good copy.
+        let replaced: usize = RepairHandler::repair_file(&files.file_path(), &stored, &[3], &[]).unwrap();
+
+        assert_eq!(replaced, 0);
+        assert_eq!(fs::read(files.file_path()).unwrap(), contents);
+        assert!(!files.journal_path().exists());
+    }
+
+    #[test]
+    fn drops_a_journal_that_was_not_completely_written() {
+        let files: TestFiles = TestFiles::new("incomplete");
+        let (stored, replica, contents) = setup(&files);
+        corrupt(&files, &stored, &[2]);
+        let mut journal: JournalWriter = JournalWriter::create(&files.journal_path(), 0).unwrap();
+        journal.append(0, b"never applied").unwrap();
+        drop(journal);
+        assert!(JournalReader::open(&files.journal_path()).unwrap().is_none());
+
+        let replaced: usize = RepairHandler::repair_file(&files.file_path(), &stored, &[2], &[&replica]).unwrap();
+
+        assert_eq!(replaced, 1);
+        assert_eq!(fs::read(files.file_path()).unwrap(), contents);
+        assert!(!files.journal_path().exists());
+    }
+
+    #[test]
+    fn refuses_chunks_without_a_position() {
+        let files: TestFiles = TestFiles::new("positions");
+        let (mut stored, replica, _) = setup(&files);
+        stored.chunk_positions.truncate(4);
+
+        assert!(RepairHandler::repair_file(&files.file_path(), &stored, &[], &[&replica]).is_err());
+        assert!(!files.journal_path().exists());
+    }
+}
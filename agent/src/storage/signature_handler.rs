@@ -1,201 +1,248 @@
-use rs_merkle::{Hasher, MerkleTree};
+use chrono::{DateTime, Utc};
+use rs_merkle::MerkleTree;
 use rs_merkle::algorithms::Sha256 as MerkleHasher;
-use mongodb::{bson::doc, error::Result, options::ClientOptions, Client, Collection};
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
-use std::io::{Read, BufReader};
+use std::io::BufReader;
 use log::{info, error, warn};
-use hex;
 
+use crate::security::signing_handler::SigningHandler;
+use crate::storage::chunk_diff::{diff_chunks, ChunkChange, ChunkChangeKind};
+use crate::storage::chunker::{hash_ranges, Chunk, Chunker, ChunkerVersion};
+use crate::storage::signature_store::{SignatureStore, StoreResult};
 use crate::utils::constants::{
-    COLLECTION_NAME_CATALOG,
-    COLLECTION_NAME_SIGNATURES,
-    CDC_WINDOW_SIZE,
-    CDC_AVERAGE_CHUNK_SIZE,
-    CDC_MASK
+    CATALOG_VERIFIED_STATUSES,
+    CDC_CHUNKER_VERSION,
+    CDC_CHUNKER_VERSION_ADDITIVE,
+    RECORD_SIGNATURE_DOMAIN
 };
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+/// One version of a file's signature. Records are never rewritten: a
+/// legitimate change is stored as a new version, and the latest version is
+/// the one files are verified against. Records made before versioning
+/// have no version, read as 0, and are refused when loaded.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Signature {
-    file_name: String,
-    signature: String,
-    leaves: Vec<String>,
-    chunk_positions: Vec<usize>
+    pub file_name: String,
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub signature: String,
+    pub leaves: Vec<String>,
+    pub chunk_positions: Vec<usize>,
+    #[serde(default = "legacy_chunker_version")]
+    pub chunker_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_key_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record_signature: Option<String>
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
-pub struct Catalog {
-    file_name: String
+impl Signature {
+    /// Bytes covered by `record_signature`. Every field is length-prefixed
+    /// so that no two different records share an encoding.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        let positions: Vec<u8> = self.chunk_positions
+            .iter()
+            .flat_map(|position| (*position as u64).to_le_bytes())
+            .collect();
+
+        push_field(&mut bytes, RECORD_SIGNATURE_DOMAIN);
+        push_field(&mut bytes, self.file_name.as_bytes());
+        push_field(&mut bytes, self.signature.as_bytes());
+        push_field(&mut bytes, self.leaves.join(",").as_bytes());
+        push_field(&mut bytes, &positions);
+        push_field(&mut bytes, &self.chunker_version.to_le_bytes());
+        push_field(&mut bytes, self.signing_key_id.as_deref().unwrap_or_default().as_bytes());
+        push_field(&mut bytes, &self.version.to_le_bytes());
+        push_field(&mut bytes, self.created_at.as_deref().unwrap_or_default().as_bytes());
+        push_field(&mut bytes, self.author.as_deref().unwrap_or_default().as_bytes());
+        push_field(&mut bytes, self.reason.as_deref().unwrap_or_default().as_bytes());
+
+        bytes
+    }
+
+    /// When the version was recorded. A record without a timestamp counts
+    /// as older than any that has one.
+    pub fn recorded_at(&self) -> Option<DateTime<Utc>> {
+        self.created_at
+            .as_deref()
+            .and_then(|created_at| DateTime::parse_from_rfc3339(created_at).ok())
+            .map(|created_at| created_at.with_timezone(&Utc))
+    }
 }
 
-pub struct SignatureHandler {
-    signatures: Collection<Signature>
+fn push_field(bytes: &mut Vec<u8>, field: &[u8]) {
+    bytes.extend_from_slice(&(field.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(field);
 }
 
-impl SignatureHandler {
-    pub async fn new(db_url: &str, db_name: &str) -> Self {
-        let client_options: ClientOptions = ClientOptions::parse(db_url)
-            .await
-            .expect("Failed to parse MongoDB URL");
-            
-        let client: Client = Client::with_options(client_options)
-            .expect("Failed to connect to MongoDB");
-            
-        let database: mongodb::Database = client.database(db_name);
-        let signatures: Collection<Signature> = database.collection::<Signature>(COLLECTION_NAME_SIGNATURES);
+fn legacy_chunker_version() -> u32 {
+    CDC_CHUNKER_VERSION_ADDITIVE
+}
 
-        info!("Connected to storage");
+/// Who accepts a change to a file and why; both end up in the signature
+/// version the change is recorded as.
+pub struct AcceptOptions {
+    pub author: String,
+    pub reason: String,
+    /// Record the new version even though the current record failed
+    /// verification; the override is noted in the version's reason.
+    pub override_tampered: bool,
+}
 
-        Self {
-            signatures
-        }
-    }
+/// What the catalog knows about one tracked file. Metadata is the last
+/// seen on disk, so a file that went missing keeps its previous values.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Catalog {
+    pub file_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_at: Option<String>,
+    /// Permission bits in octal, such as `644`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_uid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_gid: Option<u32>,
+    pub first_seen: String,
+    /// Last time the content was found to match its signature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_verified: Option<String>,
+    pub last_status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature_version: Option<u32>,
+}
 
-    fn rolling_hash(buffer: &[u8], window_size: usize) -> Vec<u32> {
-        if buffer.len() < window_size {
-            return vec![0];
-        }
-        let mut hashes = Vec::with_capacity(buffer.len() - window_size + 1);
-        let mut hash: u32 = 0;
-        for i in 0..window_size {
-            hash = hash.wrapping_add(buffer[i] as u32);
+impl Catalog {
+    /// Entry for `file_name` after a run that left it with `status`,
+    /// carrying over what `previous` knew that this run did not see.
+    pub fn observe(previous: Option<&Catalog>, file_name: &str, file_path: &str, status: &str, signature_version: Option<u32>, now: &str) -> Self {
+        let mut entry: Catalog = previous.cloned().unwrap_or_else(|| Catalog {
+            file_name: file_name.to_string(),
+            size: None,
+            modified_at: None,
+            permissions: None,
+            owner_uid: None,
+            owner_gid: None,
+            first_seen: now.to_string(),
+            last_verified: None,
+            last_status: String::new(),
+            signature_version: None,
+        });
+
+        if let Ok(metadata) = fs::symlink_metadata(file_path) {
+            entry.size = Some(metadata.len());
+            entry.modified_at = metadata.modified()
+                .ok()
+                .map(|modified| DateTime::<Utc>::from(modified).to_rfc3339());
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                entry.permissions = Some(format!("{:o}", metadata.mode() & 0o7777));
+                entry.owner_uid = Some(metadata.uid());
+                entry.owner_gid = Some(metadata.gid());
+            }
         }
-        hashes.push(hash);
-        for i in window_size..buffer.len() {
-            hash = hash.wrapping_add(buffer[i] as u32);
-            hash = hash.wrapping_sub(buffer[i - window_size] as u32);
-            hashes.push(hash);
+        if CATALOG_VERIFIED_STATUSES.contains(&status) {
+            entry.last_verified = Some(now.to_string());
         }
-        
-        hashes
+        entry.last_status = status.to_string();
+        entry.signature_version = signature_version.or(entry.signature_version);
+
+        entry
     }
-    fn find_chunk_boundaries(&self, buffer: &[u8]) -> Vec<usize> {
-        let hashes = Self::rolling_hash(buffer, CDC_WINDOW_SIZE);
-        let mut boundaries = Vec::new();
-        boundaries.push(0);
-        
-        let mut current_chunk_size = 0;
-        let min_chunk_size = CDC_AVERAGE_CHUNK_SIZE / 4;
-        let max_chunk_size = CDC_AVERAGE_CHUNK_SIZE * 4;
-        
-        for (i, hash) in hashes.iter().enumerate() {
-            current_chunk_size += 1;
-            
-            if current_chunk_size >= min_chunk_size {
-                if (hash & CDC_MASK) == 0 || current_chunk_size >= max_chunk_size {
-                    let boundary = i + CDC_WINDOW_SIZE;
-                    if boundary < buffer.len() {
-                        boundaries.push(boundary);
-                        current_chunk_size = 0;
-                    }
-                }
-            }
-        }
-        if boundaries.last() != Some(&buffer.len()) {
-            boundaries.push(buffer.len());
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    /// The signature store could not be read.
+    Store(String),
+    /// The record is not signed by the agent's key, or its signature does
+    /// not match its contents.
+    Tampered(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Store(e) => write!(f, "signature store error: {}", e),
+            LoadError::Tampered(e) => write!(f, "signature record failed verification: {}", e),
         }
-        
-        boundaries
     }
+}
+
+pub struct SignatureHandler {
+    store: Box<dyn SignatureStore>,
+    signing_handler: SigningHandler,
+    allow_unsigned: bool
+}
 
-    fn get_chunks<'a>(&self, buffer: &'a [u8], boundaries: &[usize]) -> Vec<&'a [u8]> {
-        let mut chunks = Vec::new();
-        
-        for i in 0..boundaries.len() - 1 {
-            let start = boundaries[i];
-            let end = boundaries[i + 1];
-            chunks.push(&buffer[start..end]);
+impl SignatureHandler {
+    pub fn new(store: Box<dyn SignatureStore>, signing_handler: SigningHandler, allow_unsigned: bool) -> Self {
+        Self {
+            store,
+            signing_handler,
+            allow_unsigned
         }
-        
-        chunks
     }
 
-    pub fn generate_signature(&self, file_path: &str) -> String {
-        let file_result = fs::File::open(file_path);
-        let file = match file_result {
+    fn chunk_file(&self, file_path: &str, chunker_version: u32, chunk_positions: Option<&[usize]>) -> std::result::Result<Vec<Chunk>, String> {
+        let version: ChunkerVersion = match ChunkerVersion::from_version(chunker_version) {
+            Some(version) => version,
+            None => return Err(format!("Unknown chunker version: {}", chunker_version)),
+        };
+        let file: fs::File = match fs::File::open(file_path) {
             Ok(file) => file,
             Err(e) => {
                 error!("Failed to open file {}: {}", file_path, e);
-                return String::new();
+                return Err(format!("Failed to open file: {}", e));
             }
         };
-        
-        let mut buffer: Vec<u8> = Vec::new();
-        let mut file_reader: BufReader<fs::File> = BufReader::new(file);
-    
-        info!("Generating content-defined chunking hash for file: {}", file_path);
-        
-        if let Err(e) = file_reader.read_to_end(&mut buffer) {
-            error!("Failed to read file {}: {}", file_path, e);
-            return String::new();
-        }
-    
-        if buffer.is_empty() {
-            error!("File is empty, cannot generate signature");
-            return String::new();
-        }
+        let file_reader: BufReader<fs::File> = BufReader::new(file);
 
-        let boundaries = self.find_chunk_boundaries(&buffer);
-        let chunks = self.get_chunks(&buffer, &boundaries);
-        let leaves: Vec<[u8; 32]> = chunks
-            .iter()
-            .map(|chunk| MerkleHasher::hash(chunk))
-            .collect();
-        
-        if leaves.is_empty() {
-            error!("No chunks generated, cannot create signature");
-            return String::new();
-        }
-    
-        let merkle_tree = MerkleTree::<MerkleHasher>::from_leaves(&leaves);
-        let root = match merkle_tree.root() {
-            Some(root) => root,
-            None => {
-                error!("Merkle tree is empty — failed to calculate root");
-                return String::new();
-            }
+        let chunks = match chunk_positions {
+            Some(positions) => hash_ranges(file_reader, positions),
+            None => Chunker::new(file_reader, version).collect(),
         };
-    
-        hex::encode(root)
+
+        chunks.map_err(|e| {
+            error!("Failed to read file {}: {}", file_path, e);
+            format!("Failed to read file: {}", e)
+        })
     }
-    
+
+    fn merkle_root(leaves: &[[u8; 32]]) -> Option<[u8; 32]> {
+        MerkleTree::<MerkleHasher>::from_leaves(leaves).root()
+    }
+
     pub fn generate_signature_with_leaves(&self, file_path: &str) -> (String, Vec<String>, Vec<usize>) {
-        let file_result = fs::File::open(file_path);
-        let file = match file_result {
-            Ok(file) => file,
-            Err(e) => {
-                error!("Failed to open file {}: {}", file_path, e);
-                return (String::new(), Vec::new(), Vec::new());
-            }
+        let chunks: Vec<Chunk> = match self.chunk_file(file_path, CDC_CHUNKER_VERSION, None) {
+            Ok(chunks) => chunks,
+            Err(_) => return (String::new(), Vec::new(), Vec::new()),
         };
-        
-        let mut buffer = Vec::new();
-        let mut file_reader = BufReader::new(file);
-        
-        if let Err(e) = file_reader.read_to_end(&mut buffer) {
-            error!("Failed to read file {}: {}", file_path, e);
-            return (String::new(), Vec::new(), Vec::new());
-        }
-    
-        if buffer.is_empty() {
+
+        if chunks.is_empty() {
             error!("File is empty, cannot generate signature");
             return (String::new(), Vec::new(), Vec::new());
         }
-        let boundaries = self.find_chunk_boundaries(&buffer);
-        let chunks = self.get_chunks(&buffer, &boundaries);
-        
-        let leaves: Vec<[u8; 32]> = chunks
-            .iter()
-            .map(|chunk| MerkleHasher::hash(chunk))
-            .collect();
-        
-        if leaves.is_empty() {
-            error!("No chunks generated, cannot create signature");
-            return (String::new(), Vec::new(), Vec::new());
-        }
-        let leaf_strings: Vec<String> = leaves.iter().map(|leaf| hex::encode(leaf)).collect();
-    
-        let merkle_tree = MerkleTree::<MerkleHasher>::from_leaves(&leaves);
-        let root = match merkle_tree.root() {
+
+        let leaves: Vec<[u8; 32]> = chunks.iter().map(|chunk| chunk.hash).collect();
+        let leaf_strings: Vec<String> = leaves.iter().map(hex::encode).collect();
+        let mut boundaries: Vec<usize> = Vec::with_capacity(chunks.len() + 1);
+        boundaries.push(0);
+        boundaries.extend(chunks.iter().map(|chunk| chunk.offset + chunk.length));
+
+        let root = match Self::merkle_root(&leaves) {
             Some(root) => root,
             None => {
                 error!("Merkle tree is empty — failed to calculate root");
@@ -207,23 +254,9 @@ impl SignatureHandler {
         (hex::encode(root), leaf_strings, boundaries)
     }
 
-    pub fn check_broken_chunks(&self, file_path: &str, original_signature: &str, original_leaves_hex: &[String], chunk_positions: Option<&[usize]>) -> std::result::Result<Vec<usize>, String> {
-        let original_root = match hex::decode(original_signature) {
-            Ok(bytes) => {
-                if bytes.len() != 32 {
-                    return Err("Invalid signature length".to_string());
-                }
-                let mut root = [0u8; 32];
-                root.copy_from_slice(&bytes);
-                root
-            },
-            Err(e) => {
-                error!("Failed to decode signature: {}", e);
-                return Err(format!("Failed to decode signature: {}", e));
-            }
-        };
-        let mut original_leaves: Vec<[u8; 32]> = Vec::new();
-        for hex_str in original_leaves_hex {
+    fn decode_leaves(leaves_hex: &[String]) -> std::result::Result<Vec<[u8; 32]>, String> {
+        let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(leaves_hex.len());
+        for hex_str in leaves_hex {
             match hex::decode(hex_str) {
                 Ok(bytes) => {
                     if bytes.len() != 32 {
@@ -231,51 +264,45 @@ impl SignatureHandler {
                     }
                     let mut leaf = [0u8; 32];
                     leaf.copy_from_slice(&bytes);
-                    original_leaves.push(leaf);
+                    leaves.push(leaf);
                 },
                 Err(e) => {
                     return Err(format!("Failed to decode leaf hash: {}", e));
                 }
             }
         }
-        let file: fs::File = match fs::File::open(file_path) {
-            Ok(file) => file,
+
+        Ok(leaves)
+    }
+
+    /// Compares a file with its stored signature and returns how its chunks
+    /// changed, none when it is intact. A changed file is chunked again by
+    /// content and aligned against the stored leaves, so that an insertion
+    /// shows as the chunks it added rather than as every chunk after it.
+    pub fn check_broken_chunks(&self, file_path: &str, stored: &Signature) -> std::result::Result<Vec<ChunkChange>, String> {
+        let original_root = match hex::decode(&stored.signature) {
+            Ok(bytes) => {
+                if bytes.len() != 32 {
+                    return Err("Invalid signature length".to_string());
+                }
+                let mut root = [0u8; 32];
+                root.copy_from_slice(&bytes);
+                root
+            },
             Err(e) => {
-                error!("Failed to open file {}: {}", file_path, e);
-                return Err(format!("Failed to open file: {}", e));
+                error!("Failed to decode signature: {}", e);
+                return Err(format!("Failed to decode signature: {}", e));
             }
         };
-        
-        let mut buffer: Vec<u8> = Vec::new();
-        let mut file_reader = BufReader::new(file);
-        
-        if let Err(e) = file_reader.read_to_end(&mut buffer) {
-            error!("Failed to read file {}: {}", file_path, e);
-            return Err(format!("Failed to read file: {}", e));
-        }
-    
-        if buffer.is_empty() {
+        let original_leaves: Vec<[u8; 32]> = Self::decode_leaves(&stored.leaves)?;
+
+        let chunks: Vec<Chunk> = self.chunk_file(file_path, stored.chunker_version, Some(&stored.chunk_positions))?;
+        if chunks.is_empty() {
             return Err("File is empty".to_string());
         }
-    
-        let (current_leaves, boundaries) = if let Some(positions) = chunk_positions {
-            let chunks = self.get_chunks(&buffer, positions);
-            let leaves: Vec<[u8; 32]> = chunks
-                .iter()
-                .map(|chunk| MerkleHasher::hash(chunk))
-                .collect();
-            (leaves, positions.to_vec())
-        } else {
-            let boundaries = self.find_chunk_boundaries(&buffer);
-            let chunks = self.get_chunks(&buffer, &boundaries);
-            let leaves: Vec<[u8; 32]> = chunks
-                .iter()
-                .map(|chunk| MerkleHasher::hash(chunk))
-                .collect();
-            (leaves, boundaries)
-        };
-        let merkle_tree = MerkleTree::<MerkleHasher>::from_leaves(&current_leaves);
-        let current_root = match merkle_tree.root() {
+        let current_leaves: Vec<[u8; 32]> = chunks.iter().map(|chunk| chunk.hash).collect();
+
+        let current_root = match Self::merkle_root(&current_leaves) {
             Some(root) => root,
             None => {
                 return Err("Failed to calculate Merkle root".to_string());
@@ -286,61 +313,295 @@ impl SignatureHandler {
         }
     
         info!("File signature mismatch detected. Current: {}, Original: {}", 
-            hex::encode(current_root), original_signature);
-        let mut corrupted_chunks: Vec<usize> = Vec::new();
-        let min_len = std::cmp::min(current_leaves.len(), original_leaves.len());
-        for i in 0..min_len {
-            if current_leaves[i] != original_leaves[i] {
-                corrupted_chunks.push(i);
-            }
-        }
-        if current_leaves.len() > original_leaves.len() {
-            for i in original_leaves.len()..current_leaves.len() {
-                corrupted_chunks.push(i);
-            }
-        } else if original_leaves.len() > current_leaves.len() {
-            for i in current_leaves.len()..original_leaves.len() {
-                corrupted_chunks.push(i);
-            }
-        }
-        if corrupted_chunks.is_empty() {
+            hex::encode(current_root), stored.signature);
+        let current_chunks: Vec<Chunk> = self.chunk_file(file_path, stored.chunker_version, None)?;
+        let mut changes: Vec<ChunkChange> = diff_chunks(&original_leaves, &stored.chunk_positions, &current_chunks);
+        info!("Realigned {} current chunks against {} stored leaves for {}: {} changes",
+            current_chunks.len(), original_leaves.len(), file_path, changes.len());
+        if changes.is_empty() {
             warn!("File '{}' has changed but specific corrupted chunks couldn't be identified", file_path);
-            corrupted_chunks = (0..original_leaves.len()).collect();
+            changes = (0..original_leaves.len())
+                .map(|index| ChunkChange {
+                    kind: ChunkChangeKind::Modified,
+                    original_index: Some(index),
+                    original_range: stored.chunk_positions.get(index).zip(stored.chunk_positions.get(index + 1))
+                        .map(|(&start, &end)| start..end),
+                    current_index: None,
+                    current_range: None,
+                })
+                .collect();
         }
     
-        Ok(corrupted_chunks)
+        Ok(changes)
     }
 
-    pub async fn save_signature(&self, file_name: &str, signature: &str, leaves: &[String], chunk_positions: &[usize]) -> Result<()> {
-        let signature_doc = Signature {
+    /// Records a new version of the signature of `file_name` and returns
+    /// the record as stored.
+    pub async fn save_signature(&self, file_name: &str, signature: &str, leaves: &[String], chunk_positions: &[usize], author: &str, reason: &str) -> StoreResult<Signature> {
+        let version: u32 = self.store
+            .history(file_name)
+            .await?
+            .iter()
+            .map(|previous| previous.version)
+            .max()
+            .map_or(1, |latest| latest + 1);
+        let mut signature_doc = Signature {
             file_name: file_name.to_string(),
+            version,
+            created_at: Some(Utc::now().to_rfc3339()),
+            author: Some(author.to_string()),
+            reason: Some(reason.to_string()),
             signature: signature.to_string(),
             leaves: leaves.to_vec(),
             chunk_positions: chunk_positions.to_vec(),
+            chunker_version: CDC_CHUNKER_VERSION,
+            signing_key_id: Some(self.signing_handler.key_id().to_string()),
+            record_signature: None,
         };
+        signature_doc.record_signature = Some(self.signing_handler.sign(&signature_doc.canonical_bytes()));
     
-        info!("Saving signature and {} leaf hashes for {}", leaves.len(), file_name);
-        self.signatures
-            .insert_one(signature_doc)
-            .await?;
-    
-        Ok(())
+        info!("Saving signature version {} and {} leaf hashes for {}", version, leaves.len(), file_name);
+        self.store.save(&signature_doc).await?;
+
+        Ok(signature_doc)
     }
 
-    pub async fn load_signature_with_leaves(&self, file_name: &str) -> Option<(String, Vec<String>, Vec<usize>)> {
-        let query: mongodb::bson::Document = doc! { "file_name": file_name };
-    
+    pub async fn load_signature_with_leaves(&self, file_name: &str) -> std::result::Result<Option<Signature>, LoadError> {
         info!("Loading signature and leaf hashes for {}", file_name);
-        match self.signatures.find_one(query).await {
-            Ok(Some(doc)) => Some((doc.signature, doc.leaves, doc.chunk_positions)),
+        match self.store.load(file_name).await {
+            Ok(Some(doc)) => {
+                self.verify_record(&doc)?;
+                Ok(Some(doc))
+            }
             Ok(None) => {
                 info!("No signature found for {}", file_name);
-                None
+                Ok(None)
             }
             Err(e) => {
                 error!("Failed to load signature: {:?}", e);
-                None
+                Err(LoadError::Store(e.to_string()))
+            }
+        }
+    }
+
+    /// Loads the version of `file_name` that was current at `at`.
+    pub async fn load_signature_at(&self, file_name: &str, at: DateTime<Utc>) -> std::result::Result<Option<Signature>, LoadError> {
+        info!("Loading the signature of {} as of {}", file_name, at.to_rfc3339());
+        let history: Vec<Signature> = self.store
+            .history(file_name)
+            .await
+            .map_err(|e| LoadError::Store(e.to_string()))?;
+        let current: Option<Signature> = history
+            .into_iter()
+            .filter(|doc| doc.recorded_at().is_none_or(|recorded_at| recorded_at <= at))
+            .max_by_key(|doc| doc.version);
+
+        match current {
+            Some(doc) => {
+                self.verify_record(&doc)?;
+                Ok(Some(doc))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn verify_record(&self, doc: &Signature) -> std::result::Result<(), LoadError> {
+        if doc.version == 0 {
+            error!("Signature record for '{}' has no version", doc.file_name);
+            return Err(LoadError::Tampered("record has no version".to_string()));
+        }
+        match (&doc.signing_key_id, &doc.record_signature) {
+            (Some(key_id), Some(record_signature)) => {
+                if self.signing_handler.verify(&doc.canonical_bytes(), key_id, record_signature) {
+                    return Ok(());
+                }
+                error!("Signature record for '{}' does not verify against key {}", doc.file_name, self.signing_handler.key_id());
+                Err(LoadError::Tampered(format!("record is not signed by key {}", self.signing_handler.key_id())))
+            }
+            (None, None) if self.allow_unsigned => {
+                warn!("Accepting unsigned signature record for '{}'", doc.file_name);
+                Ok(())
+            }
+            _ => {
+                error!("Signature record for '{}' is unsigned", doc.file_name);
+                Err(LoadError::Tampered("record is unsigned".to_string()))
             }
         }
     }
+
+    pub async fn load_catalog(&self) -> StoreResult<Vec<Catalog>> {
+        self.store.load_catalog().await
+    }
+
+    pub async fn save_catalog(&self, entries: &[Catalog]) -> StoreResult<()> {
+        info!("Updating {} catalog entries", entries.len());
+        self.store.save_catalog(entries).await
+    }
+
+    /// The signature version the catalog last saw for each file, which
+    /// outlives a signature record removed from the store.
+    pub async fn tracked_versions(&self) -> StoreResult<HashMap<String, u32>> {
+        let entries: Vec<Catalog> = self.store.load_catalog().await?;
+
+        Ok(entries
+            .into_iter()
+            .filter_map(|entry| entry.signature_version.map(|version| (entry.file_name, version)))
+            .collect())
+    }
+
+    /// Removes every signature version and the catalog entry of
+    /// `file_name`, and returns how many versions there were.
+    pub async fn forget(&self, file_name: &str) -> StoreResult<u64> {
+        self.store.delete(file_name).await
+    }
+
+    pub async fn list_signatures(&self) -> StoreResult<Vec<String>> {
+        self.store.list().await
+    }
+
+    /// Every record kept for `file_name`, oldest first, as stored.
+    pub async fn signature_history(&self, file_name: &str) -> StoreResult<Vec<Signature>> {
+        self.store.history(file_name).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::stores::file_store::FileSignatureStore;
+    use crate::utils::constants::SIGNATURE_AUTHOR_AGENT;
+    use std::path::PathBuf;
+
+    /// Store and signing key removed once the test is over.
+    struct TestStore(PathBuf);
+
+    impl TestStore {
+        fn new(name: &str) -> Self {
+            let path: PathBuf = std::env::temp_dir().join(format!("glacier-signature-handler-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+
+            Self(path)
+        }
+
+        fn store(&self) -> FileSignatureStore {
+            FileSignatureStore::new(&self.0.join("signatures.jsonl").to_string_lossy()).unwrap()
+        }
+
+        fn signing_handler(&self) -> SigningHandler {
+            SigningHandler::load_or_create(&self.0.join("signing.key").to_string_lossy()).unwrap()
+        }
+
+        fn handler(&self, allow_unsigned: bool) -> SignatureHandler {
+            SignatureHandler::new(Box::new(self.store()), self.signing_handler(), allow_unsigned)
+        }
+    }
+
+    impl Drop for TestStore {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    async fn load(store: &TestStore, allow_unsigned: bool) -> std::result::Result<Option<Signature>, LoadError> {
+        store.handler(allow_unsigned).load_signature_with_leaves("a.bin").await
+    }
+
+    #[tokio::test]
+    async fn covers_the_version_and_its_metadata() {
+        let store: TestStore = TestStore::new("covered");
+        let saved: Signature = store.handler(false)
+            .save_signature("a.bin", "root", &["leaf".to_string()], &[0, 4], "alice", "initial")
+            .await
+            .unwrap();
+        assert_eq!(load(&store, false).await.unwrap().map(|stored| stored.version), Some(1));
+
+        let mut later: Signature = saved.clone();
+        later.version = 2;
+        store.store().save(&later).await.unwrap();
+        assert!(matches!(load(&store, false).await, Err(LoadError::Tampered(_))));
+
+        let saved_copy: Signature = saved.clone();
+        let mut reworded: Signature = saved;
+        reworded.version = 3;
+        reworded.reason = Some("approved".to_string());
+        reworded.record_signature = Some(store.signing_handler().sign(&Signature { version: 3, ..saved_copy }.canonical_bytes()));
+        store.store().save(&reworded).await.unwrap();
+        assert!(matches!(load(&store, false).await, Err(LoadError::Tampered(_))));
+    }
+
+    #[tokio::test]
+    async fn refuses_records_without_a_version() {
+        let store: TestStore = TestStore::new("versionless");
+        let signing_handler: SigningHandler = store.signing_handler();
+        let mut record: Signature = serde_json::from_str(
+            r#"{"file_name":"a.bin","signature":"root","leaves":["leaf"],"chunk_positions":[0,4]}"#
+        ).unwrap();
+        assert_eq!(record.version, 0);
+        store.store().save(&record).await.unwrap();
+        assert!(matches!(load(&store, true).await, Err(LoadError::Tampered(_))));
+
+        record.signing_key_id = Some(signing_handler.key_id().to_string());
+        record.record_signature = Some(signing_handler.sign(&record.canonical_bytes()));
+        store.store().save(&record).await.unwrap();
+        assert!(matches!(load(&store, false).await, Err(LoadError::Tampered(_))));
+    }
+
+    #[tokio::test]
+    async fn accepting_a_change_records_the_next_version() {
+        let store: TestStore = TestStore::new("accepted");
+        let handler: SignatureHandler = store.handler(false);
+        let first: Signature = handler
+            .save_signature("a.bin", "root", &["leaf".to_string()], &[0, 4], SIGNATURE_AUTHOR_AGENT, "first seen")
+            .await
+            .unwrap();
+        let accepted: Signature = handler
+            .save_signature("a.bin", "changed", &["other".to_string()], &[0, 4], "alice", "new release")
+            .await
+            .unwrap();
+        assert_eq!((first.version, accepted.version), (1, 2));
+
+        let current: Signature = load(&store, false).await.unwrap().unwrap();
+        assert_eq!(current.version, 2);
+        assert_eq!(current.signature, "changed");
+        assert_eq!(current.author.as_deref(), Some("alice"));
+        assert_eq!(current.reason.as_deref(), Some("new release"));
+
+        let earlier: Signature = handler.load_signature_at("a.bin", first.recorded_at().unwrap()).await.unwrap().unwrap();
+        assert_eq!(earlier.version, 1);
+        assert_eq!(handler.signature_history("a.bin").await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn observing_a_file_updates_its_catalog_entry() {
+        let store: TestStore = TestStore::new("catalog");
+        let path: PathBuf = store.0.join("a.bin");
+        let file_path: String = path.to_string_lossy().to_string();
+        fs::write(&path, b"content").unwrap();
+
+        let seen: Catalog = Catalog::observe(None, "a.bin", &file_path, "initialized", Some(1), "t1");
+        assert_eq!(seen.first_seen, "t1");
+        assert_eq!(seen.size, Some(7));
+        assert!(seen.modified_at.is_some());
+        assert_eq!(seen.last_verified.as_deref(), Some("t1"));
+        assert_eq!(seen.last_status, "initialized");
+        assert_eq!(seen.signature_version, Some(1));
+
+        fs::write(&path, b"changed content").unwrap();
+        let corrupted: Catalog = Catalog::observe(Some(&seen), "a.bin", &file_path, "corrupted", None, "t2");
+        assert_eq!(corrupted.first_seen, "t1");
+        assert_eq!(corrupted.size, Some(15));
+        assert_eq!(corrupted.last_verified.as_deref(), Some("t1"));
+        assert_eq!(corrupted.last_status, "corrupted");
+        assert_eq!(corrupted.signature_version, Some(1));
+
+        fs::remove_file(&path).unwrap();
+        let missing: Catalog = Catalog::observe(Some(&corrupted), "a.bin", &file_path, "missing", None, "t3");
+        assert_eq!(missing.size, Some(15));
+        assert_eq!(missing.last_status, "missing");
+
+        let accepted: Catalog = Catalog::observe(Some(&missing), "a.bin", &file_path, "accepted", Some(2), "t4");
+        assert_eq!(accepted.last_verified.as_deref(), Some("t4"));
+        assert_eq!(accepted.signature_version, Some(2));
+    }
 }
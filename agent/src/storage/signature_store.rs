@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+use log::info;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use crate::config::environment::Environment;
+use crate::storage::signature_handler::{Catalog, Signature};
+use crate::storage::stores::file_store::FileSignatureStore;
+use crate::storage::stores::mongo_store::MongoSignatureStore;
+use crate::storage::stores::sqlite_store::SqliteSignatureStore;
+use crate::utils::constants::{
+    SIGNATURE_STORE_FILE,
+    SIGNATURE_STORE_MONGODB,
+    SIGNATURE_STORE_SQLITE
+};
+
+pub type StoreResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Persistence backend for file signatures. Records are append-only: `save`
+/// adds a version, `load` returns the latest version, which is the one used
+/// for verification, and `history` returns every version kept for a file,
+/// oldest first. Only `delete` removes records, along with the catalog
+/// entry, when a file is forgotten.
+///
+/// The store also keeps the catalog, one entry per tracked file.
+/// `save_catalog` inserts or replaces the entries it is given and keeps
+/// every other one.
+#[async_trait]
+pub trait SignatureStore: Send + Sync {
+    async fn save(&self, signature: &Signature) -> StoreResult<()>;
+    async fn load(&self, file_name: &str) -> StoreResult<Option<Signature>>;
+    async fn list(&self) -> StoreResult<Vec<String>>;
+    async fn delete(&self, file_name: &str) -> StoreResult<u64>;
+    async fn history(&self, file_name: &str) -> StoreResult<Vec<Signature>>;
+    async fn save_catalog(&self, entries: &[Catalog]) -> StoreResult<()>;
+    async fn load_catalog(&self) -> StoreResult<Vec<Catalog>>;
+}
+
+/// Runs `work` on the state of an embedded store from the blocking thread
+/// pool, so that its locking and disk IO never stall the async runtime.
+pub async fn run_blocking<S, T>(state: &Arc<Mutex<S>>, work: impl FnOnce(&mut S) -> StoreResult<T> + Send + 'static) -> StoreResult<T>
+where
+    S: Send + 'static,
+    T: Send + 'static
+{
+    let state: Arc<Mutex<S>> = Arc::clone(state);
+
+    tokio::task::spawn_blocking(move || {
+        let mut state = state.lock().map_err(|_| "Signature store lock poisoned")?;
+        work(&mut state)
+    })
+    .await?
+}
+
+pub async fn open_signature_store(env: &Environment) -> StoreResult<Box<dyn SignatureStore>> {
+    info!("Opening {} signature store", env.signature_store);
+
+    match env.signature_store.as_str() {
+        SIGNATURE_STORE_MONGODB => {
+            let store = MongoSignatureStore::new(&env.database_url, &env.database_name).await?;
+            Ok(Box::new(store))
+        }
+        SIGNATURE_STORE_SQLITE => Ok(Box::new(SqliteSignatureStore::new(&env.signature_store_path)?)),
+        SIGNATURE_STORE_FILE => Ok(Box::new(FileSignatureStore::new(&env.signature_store_path)?)),
+        other => Err(format!("Unknown signature store: {}", other).into()),
+    }
+}
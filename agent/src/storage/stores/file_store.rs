@@ -0,0 +1,341 @@
+use async_trait::async_trait;
+use log::info;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::storage::signature_handler::{Catalog, Signature};
+use crate::storage::signature_store::{run_blocking, SignatureStore, StoreResult};
+
+/// Flat-file store appending one JSON document per line, meant for
+/// air-gapped machines where neither MongoDB nor SQLite is wanted. The
+/// catalog is a JSON list next to it, rewritten on every update.
+///
+/// Records are indexed in memory by file name. Each access first reads
+/// only the lines appended since the previous one, by this process or
+/// another, so lookups do not re-read the whole file.
+pub struct FileSignatureStore {
+    state: Arc<Mutex<FileState>>
+}
+
+struct FileState {
+    path: String,
+    /// Every record read so far, by file name, oldest first.
+    index: BTreeMap<String, Vec<Signature>>,
+    /// Bytes of the file the index covers.
+    read_to: u64
+}
+
+impl FileSignatureStore {
+    pub fn new(path: &str) -> StoreResult<Self> {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        OpenOptions::new().create(true).append(true).open(path)?;
+        let mut state: FileState = FileState {
+            path: path.to_string(),
+            index: BTreeMap::new(),
+            read_to: 0
+        };
+        state.refresh()?;
+        info!("Opened flat-file signature store: {}", path);
+
+        Ok(Self {
+            state: Arc::new(Mutex::new(state))
+        })
+    }
+}
+
+impl FileState {
+    /// Adds the records appended since the last read to the index. A file
+    /// that got shorter was rewritten and is indexed again from the start;
+    /// a last line still being written is left for the next read.
+    fn refresh(&mut self) -> StoreResult<()> {
+        let mut file: File = File::open(&self.path)?;
+        let length: u64 = file.metadata()?.len();
+        if length < self.read_to {
+            self.index.clear();
+            self.read_to = 0;
+        }
+        if length == self.read_to {
+            return Ok(());
+        }
+        file.seek(SeekFrom::Start(self.read_to))?;
+        let mut reader: BufReader<File> = BufReader::new(file);
+        let mut line: String = String::new();
+
+        loop {
+            line.clear();
+            let read: usize = reader.read_line(&mut line)?;
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+            self.read_to += read as u64;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let signature: Signature = serde_json::from_str(&line)?;
+            self.index.entry(signature.file_name.clone()).or_default().push(signature);
+        }
+
+        Ok(())
+    }
+
+    fn catalog_path(&self) -> PathBuf {
+        Path::new(&self.path).with_extension("catalog.json")
+    }
+
+    fn read_catalog(&self) -> StoreResult<Vec<Catalog>> {
+        match fs::read(self.catalog_path()) {
+            Ok(contents) => Ok(serde_json::from_slice(&contents)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_catalog(&self, entries: &[&Catalog]) -> StoreResult<()> {
+        let catalog_path: PathBuf = self.catalog_path();
+        let temporary_path: PathBuf = catalog_path.with_extension("json.tmp");
+        let mut writer: BufWriter<File> = BufWriter::new(File::create(&temporary_path)?);
+        serde_json::to_writer_pretty(&mut writer, entries)?;
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&temporary_path, &catalog_path)?;
+
+        Ok(())
+    }
+
+    fn write_all(&self, signatures: &[&Signature]) -> StoreResult<()> {
+        let temporary_path: String = format!("{}.tmp", self.path);
+        let mut writer: BufWriter<File> = BufWriter::new(File::create(&temporary_path)?);
+
+        for signature in signatures {
+            serde_json::to_writer(&mut writer, signature)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&temporary_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SignatureStore for FileSignatureStore {
+    async fn save(&self, signature: &Signature) -> StoreResult<()> {
+        let mut line: Vec<u8> = serde_json::to_vec(signature)?;
+        line.push(b'\n');
+
+        run_blocking(&self.state, move |state| {
+            let mut file: File = OpenOptions::new().append(true).open(&state.path)?;
+            file.write_all(&line)?;
+            file.sync_data()?;
+
+            state.refresh()
+        }).await
+    }
+
+    async fn load(&self, file_name: &str) -> StoreResult<Option<Signature>> {
+        let file_name: String = file_name.to_string();
+
+        run_blocking(&self.state, move |state| {
+            state.refresh()?;
+
+            Ok(state.index
+                .get(&file_name)
+                .and_then(|signatures| signatures.iter().max_by_key(|signature| signature.version))
+                .cloned())
+        }).await
+    }
+
+    async fn list(&self) -> StoreResult<Vec<String>> {
+        run_blocking(&self.state, |state| {
+            state.refresh()?;
+
+            Ok(state.index.keys().cloned().collect())
+        }).await
+    }
+
+    async fn delete(&self, file_name: &str) -> StoreResult<u64> {
+        let file_name: String = file_name.to_string();
+
+        run_blocking(&self.state, move |state| {
+            state.refresh()?;
+            let deleted: u64 = state.index.get(&file_name).map_or(0, Vec::len) as u64;
+
+            if deleted > 0 {
+                let kept: Vec<&Signature> = state.index
+                    .iter()
+                    .filter(|(name, _)| **name != file_name)
+                    .flat_map(|(_, signatures)| signatures)
+                    .collect();
+                state.write_all(&kept)?;
+                state.index.remove(&file_name);
+                state.read_to = fs::metadata(&state.path)?.len();
+            }
+            let mut catalog: Vec<Catalog> = state.read_catalog()?;
+            if catalog.iter().any(|entry| entry.file_name == file_name) {
+                catalog.retain(|entry| entry.file_name != file_name);
+                state.write_catalog(&catalog.iter().collect::<Vec<&Catalog>>())?;
+            }
+
+            Ok(deleted)
+        }).await
+    }
+
+    async fn history(&self, file_name: &str) -> StoreResult<Vec<Signature>> {
+        let file_name: String = file_name.to_string();
+
+        run_blocking(&self.state, move |state| {
+            state.refresh()?;
+
+            Ok(state.index.get(&file_name).cloned().unwrap_or_default())
+        }).await
+    }
+
+    async fn save_catalog(&self, entries: &[Catalog]) -> StoreResult<()> {
+        let entries: Vec<Catalog> = entries.to_vec();
+
+        run_blocking(&self.state, move |state| {
+            let mut catalog: BTreeMap<String, Catalog> = state.read_catalog()?
+                .into_iter()
+                .map(|entry| (entry.file_name.clone(), entry))
+                .collect();
+            for entry in entries {
+                catalog.insert(entry.file_name.clone(), entry);
+            }
+
+            state.write_catalog(&catalog.values().collect::<Vec<&Catalog>>())
+        }).await
+    }
+
+    async fn load_catalog(&self) -> StoreResult<Vec<Catalog>> {
+        run_blocking(&self.state, |state| {
+            let mut entries: Vec<Catalog> = state.read_catalog()?;
+            entries.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+            Ok(entries)
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Store file and catalog removed once the test is over.
+    struct TestFile(PathBuf);
+
+    impl TestFile {
+        fn new(name: &str) -> Self {
+            let path: PathBuf = std::env::temp_dir().join(format!("glacier-file-store-{}-{}.jsonl", name, std::process::id()));
+            let test_file: TestFile = Self(path);
+            test_file.remove();
+
+            test_file
+        }
+
+        fn open(&self) -> FileSignatureStore {
+            FileSignatureStore::new(&self.0.to_string_lossy()).unwrap()
+        }
+
+        fn remove(&self) {
+            let _ = fs::remove_file(&self.0);
+            let _ = fs::remove_file(self.0.with_extension("catalog.json"));
+        }
+    }
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            self.remove();
+        }
+    }
+
+    fn signature(file_name: &str, version: u32) -> Signature {
+        Signature {
+            file_name: file_name.to_string(),
+            version,
+            created_at: None,
+            author: None,
+            reason: None,
+            signature: format!("root-{}", version),
+            leaves: vec![format!("leaf-{}", version)],
+            chunk_positions: vec![0, 10],
+            chunker_version: 2,
+            signing_key_id: None,
+            record_signature: None,
+        }
+    }
+
+    fn catalog(file_name: &str, last_status: &str) -> Catalog {
+        Catalog {
+            file_name: file_name.to_string(),
+            size: Some(10),
+            modified_at: None,
+            permissions: None,
+            owner_uid: None,
+            owner_gid: None,
+            first_seen: "2026-01-01T00:00:00+00:00".to_string(),
+            last_verified: None,
+            last_status: last_status.to_string(),
+            signature_version: Some(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_every_version_and_loads_the_latest() {
+        let test_file: TestFile = TestFile::new("versions");
+        let store: FileSignatureStore = test_file.open();
+        store.save(&signature("a.bin", 1)).await.unwrap();
+        store.save(&signature("b.bin", 1)).await.unwrap();
+        store.save(&signature("a.bin", 2)).await.unwrap();
+
+        let store: FileSignatureStore = test_file.open();
+        assert_eq!(store.load("a.bin").await.unwrap().map(|stored| stored.version), Some(2));
+        assert!(store.load("c.bin").await.unwrap().is_none());
+        assert_eq!(store.list().await.unwrap(), vec!["a.bin", "b.bin"]);
+        let history: Vec<u32> = store.history("a.bin").await.unwrap().iter().map(|stored| stored.version).collect();
+        assert_eq!(history, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn reads_records_appended_by_another_writer() {
+        let test_file: TestFile = TestFile::new("appended");
+        let reader: FileSignatureStore = test_file.open();
+        let writer: FileSignatureStore = test_file.open();
+        assert!(reader.load("a.bin").await.unwrap().is_none());
+
+        writer.save(&signature("a.bin", 1)).await.unwrap();
+        // A line still being written is left for a later read.
+        OpenOptions::new().append(true).open(&test_file.0).unwrap().write_all(b"{\"file_name\":").unwrap();
+        assert_eq!(reader.load("a.bin").await.unwrap().map(|stored| stored.version), Some(1));
+        assert_eq!(reader.list().await.unwrap(), vec!["a.bin"]);
+    }
+
+    #[tokio::test]
+    async fn upserts_catalog_entries_and_deletes_with_the_records() {
+        let test_file: TestFile = TestFile::new("catalog");
+        let store: FileSignatureStore = test_file.open();
+        store.save(&signature("a.bin", 1)).await.unwrap();
+        store.save(&signature("a.bin", 2)).await.unwrap();
+        store.save(&signature("b.bin", 1)).await.unwrap();
+        store.save_catalog(&[catalog("b.bin", "valid"), catalog("a.bin", "valid")]).await.unwrap();
+        store.save_catalog(&[catalog("a.bin", "corrupted")]).await.unwrap();
+
+        let entries: Vec<(String, String)> = store.load_catalog().await.unwrap()
+            .into_iter()
+            .map(|entry| (entry.file_name, entry.last_status))
+            .collect();
+        assert_eq!(entries, vec![("a.bin".to_string(), "corrupted".to_string()), ("b.bin".to_string(), "valid".to_string())]);
+
+        assert_eq!(store.delete("a.bin").await.unwrap(), 2);
+        assert!(store.load("a.bin").await.unwrap().is_none());
+        assert_eq!(store.list().await.unwrap(), vec!["b.bin"]);
+        assert_eq!(store.load_catalog().await.unwrap().len(), 1);
+
+        let reopened: FileSignatureStore = test_file.open();
+        assert_eq!(reopened.history("a.bin").await.unwrap().len(), 0);
+        assert_eq!(reopened.history("b.bin").await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod file_store;
+pub mod mongo_store;
+pub mod sqlite_store;
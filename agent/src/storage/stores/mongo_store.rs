@@ -0,0 +1,102 @@
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use log::info;
+use mongodb::{bson::doc, options::ClientOptions, Client, Collection};
+
+use crate::storage::signature_handler::{Catalog, Signature};
+use crate::storage::signature_store::{SignatureStore, StoreResult};
+use crate::utils::constants::{COLLECTION_NAME_CATALOG, COLLECTION_NAME_SIGNATURES};
+
+pub struct MongoSignatureStore {
+    signatures: Collection<Signature>,
+    catalog: Collection<Catalog>
+}
+
+impl MongoSignatureStore {
+    pub async fn new(db_url: &str, db_name: &str) -> StoreResult<Self> {
+        let client_options: ClientOptions = ClientOptions::parse(db_url).await?;
+        let client: Client = Client::with_options(client_options)?;
+        let database: mongodb::Database = client.database(db_name);
+        let signatures: Collection<Signature> = database.collection::<Signature>(COLLECTION_NAME_SIGNATURES);
+        let catalog: Collection<Catalog> = database.collection::<Catalog>(COLLECTION_NAME_CATALOG);
+
+        info!("Connected to storage");
+
+        Ok(Self {
+            signatures,
+            catalog
+        })
+    }
+}
+
+#[async_trait]
+impl SignatureStore for MongoSignatureStore {
+    async fn save(&self, signature: &Signature) -> StoreResult<()> {
+        self.signatures.insert_one(signature).await?;
+
+        Ok(())
+    }
+
+    async fn load(&self, file_name: &str) -> StoreResult<Option<Signature>> {
+        let signature: Option<Signature> = self.signatures
+            .find_one(doc! { "file_name": file_name })
+            .sort(doc! { "version": -1, "_id": -1 })
+            .await?;
+
+        Ok(signature)
+    }
+
+    async fn list(&self) -> StoreResult<Vec<String>> {
+        let mut file_names: Vec<String> = self.signatures
+            .distinct("file_name", doc! {})
+            .await?
+            .into_iter()
+            .filter_map(|value| value.as_str().map(str::to_string))
+            .collect();
+        file_names.sort();
+
+        Ok(file_names)
+    }
+
+    async fn delete(&self, file_name: &str) -> StoreResult<u64> {
+        let result = self.signatures
+            .delete_many(doc! { "file_name": file_name })
+            .await?;
+        self.catalog.delete_one(doc! { "file_name": file_name }).await?;
+
+        Ok(result.deleted_count)
+    }
+
+    async fn history(&self, file_name: &str) -> StoreResult<Vec<Signature>> {
+        let signatures: Vec<Signature> = self.signatures
+            .find(doc! { "file_name": file_name })
+            .sort(doc! { "_id": 1 })
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(signatures)
+    }
+
+    async fn save_catalog(&self, entries: &[Catalog]) -> StoreResult<()> {
+        for entry in entries {
+            self.catalog
+                .replace_one(doc! { "file_name": &entry.file_name }, entry)
+                .upsert(true)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn load_catalog(&self) -> StoreResult<Vec<Catalog>> {
+        let entries: Vec<Catalog> = self.catalog
+            .find(doc! {})
+            .sort(doc! { "file_name": 1 })
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(entries)
+    }
+}
@@ -0,0 +1,261 @@
+use async_trait::async_trait;
+use log::info;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::sync::{Arc, Mutex};
+
+use crate::storage::signature_handler::{Catalog, Signature};
+use crate::storage::signature_store::{run_blocking, SignatureStore, StoreResult};
+
+/// Embedded store keeping each signature as a JSON document in a single
+/// SQLite table, so no database server is needed. Queries run on the
+/// blocking thread pool.
+pub struct SqliteSignatureStore {
+    connection: Arc<Mutex<Connection>>
+}
+
+impl SqliteSignatureStore {
+    pub fn new(path: &str) -> StoreResult<Self> {
+        if let Some(parent) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let connection: Connection = Connection::open(path)?;
+
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS signatures (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                file_name TEXT NOT NULL,
+                document TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS signatures_file_name ON signatures (file_name);
+            CREATE TABLE IF NOT EXISTS catalog (
+                file_name TEXT PRIMARY KEY,
+                document TEXT NOT NULL
+            );"
+        )?;
+        info!("Opened SQLite signature store: {}", path);
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection))
+        })
+    }
+}
+
+#[async_trait]
+impl SignatureStore for SqliteSignatureStore {
+    async fn save(&self, signature: &Signature) -> StoreResult<()> {
+        let file_name: String = signature.file_name.clone();
+        let document: String = serde_json::to_string(signature)?;
+
+        run_blocking(&self.connection, move |connection| {
+            connection.execute(
+                "INSERT INTO signatures (file_name, document) VALUES (?1, ?2)",
+                params![file_name, document]
+            )?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn load(&self, file_name: &str) -> StoreResult<Option<Signature>> {
+        let file_name: String = file_name.to_string();
+        let document: Option<String> = run_blocking(&self.connection, move |connection| {
+            Ok(connection
+                .query_row(
+                    "SELECT document FROM signatures WHERE file_name = ?1
+                     ORDER BY COALESCE(json_extract(document, '$.version'), 0) DESC, id DESC LIMIT 1",
+                    params![file_name],
+                    |row| row.get(0)
+                )
+                .optional()?)
+        }).await?;
+
+        match document {
+            Some(document) => Ok(Some(serde_json::from_str(&document)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn list(&self) -> StoreResult<Vec<String>> {
+        run_blocking(&self.connection, |connection| {
+            let mut statement = connection.prepare("SELECT DISTINCT file_name FROM signatures ORDER BY file_name")?;
+            let file_names = statement
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+
+            Ok(file_names)
+        }).await
+    }
+
+    async fn delete(&self, file_name: &str) -> StoreResult<u64> {
+        let file_name: String = file_name.to_string();
+
+        run_blocking(&self.connection, move |connection| {
+            let transaction = connection.transaction()?;
+            let deleted: usize = transaction.execute(
+                "DELETE FROM signatures WHERE file_name = ?1",
+                params![file_name]
+            )?;
+            transaction.execute("DELETE FROM catalog WHERE file_name = ?1", params![file_name])?;
+            transaction.commit()?;
+
+            Ok(deleted as u64)
+        }).await
+    }
+
+    async fn history(&self, file_name: &str) -> StoreResult<Vec<Signature>> {
+        let file_name: String = file_name.to_string();
+        let documents: Vec<String> = run_blocking(&self.connection, move |connection| {
+            let mut statement = connection.prepare("SELECT document FROM signatures WHERE file_name = ?1 ORDER BY id ASC")?;
+            let documents = statement
+                .query_map(params![file_name], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+
+            Ok(documents)
+        }).await?;
+
+        let mut signatures: Vec<Signature> = Vec::with_capacity(documents.len());
+        for document in documents {
+            signatures.push(serde_json::from_str(&document)?);
+        }
+
+        Ok(signatures)
+    }
+
+    async fn save_catalog(&self, entries: &[Catalog]) -> StoreResult<()> {
+        let mut documents: Vec<(String, String)> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            documents.push((entry.file_name.clone(), serde_json::to_string(entry)?));
+        }
+
+        run_blocking(&self.connection, move |connection| {
+            let transaction = connection.transaction()?;
+
+            for (file_name, document) in &documents {
+                transaction.execute(
+                    "INSERT INTO catalog (file_name, document) VALUES (?1, ?2)
+                     ON CONFLICT (file_name) DO UPDATE SET document = excluded.document",
+                    params![file_name, document]
+                )?;
+            }
+            transaction.commit()?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn load_catalog(&self) -> StoreResult<Vec<Catalog>> {
+        let documents: Vec<String> = run_blocking(&self.connection, |connection| {
+            let mut statement = connection.prepare("SELECT document FROM catalog ORDER BY file_name")?;
+            let documents = statement
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+
+            Ok(documents)
+        }).await?;
+
+        let mut entries: Vec<Catalog> = Vec::with_capacity(documents.len());
+        for document in documents {
+            entries.push(serde_json::from_str(&document)?);
+        }
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Database file removed once the test is over.
+    struct TestDatabase(PathBuf);
+
+    impl TestDatabase {
+        fn new(name: &str) -> Self {
+            let path: PathBuf = std::env::temp_dir().join(format!("glacier-sqlite-{}-{}.db", name, std::process::id()));
+            let _ = std::fs::remove_file(&path);
+
+            Self(path)
+        }
+
+        fn open(&self) -> SqliteSignatureStore {
+            SqliteSignatureStore::new(&self.0.to_string_lossy()).unwrap()
+        }
+    }
+
+    impl Drop for TestDatabase {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn signature(file_name: &str, version: u32) -> Signature {
+        Signature {
+            file_name: file_name.to_string(),
+            version,
+            created_at: None,
+            author: None,
+            reason: None,
+            signature: format!("root-{}", version),
+            leaves: vec![format!("leaf-{}", version)],
+            chunk_positions: vec![0, 10],
+            chunker_version: 2,
+            signing_key_id: None,
+            record_signature: None,
+        }
+    }
+
+    fn catalog(file_name: &str, last_status: &str) -> Catalog {
+        Catalog {
+            file_name: file_name.to_string(),
+            size: Some(10),
+            modified_at: None,
+            permissions: None,
+            owner_uid: None,
+            owner_gid: None,
+            first_seen: "2026-01-01T00:00:00+00:00".to_string(),
+            last_verified: None,
+            last_status: last_status.to_string(),
+            signature_version: Some(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_every_version_and_loads_the_latest() {
+        let database: TestDatabase = TestDatabase::new("versions");
+        let store: SqliteSignatureStore = database.open();
+        store.save(&signature("a.bin", 1)).await.unwrap();
+        store.save(&signature("b.bin", 1)).await.unwrap();
+        store.save(&signature("a.bin", 2)).await.unwrap();
+
+        let store: SqliteSignatureStore = database.open();
+        assert_eq!(store.load("a.bin").await.unwrap().map(|stored| stored.version), Some(2));
+        assert!(store.load("c.bin").await.unwrap().is_none());
+        assert_eq!(store.list().await.unwrap(), vec!["a.bin", "b.bin"]);
+        let history: Vec<u32> = store.history("a.bin").await.unwrap().iter().map(|stored| stored.version).collect();
+        assert_eq!(history, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn upserts_catalog_entries_and_deletes_with_the_records() {
+        let database: TestDatabase = TestDatabase::new("catalog");
+        let store: SqliteSignatureStore = database.open();
+        store.save(&signature("a.bin", 1)).await.unwrap();
+        store.save(&signature("a.bin", 2)).await.unwrap();
+        store.save(&signature("b.bin", 1)).await.unwrap();
+        store.save_catalog(&[catalog("b.bin", "valid"), catalog("a.bin", "valid")]).await.unwrap();
+        store.save_catalog(&[catalog("a.bin", "corrupted")]).await.unwrap();
+
+        let entries: Vec<(String, String)> = store.load_catalog().await.unwrap()
+            .into_iter()
+            .map(|entry| (entry.file_name, entry.last_status))
+            .collect();
+        assert_eq!(entries, vec![("a.bin".to_string(), "corrupted".to_string()), ("b.bin".to_string(), "valid".to_string())]);
+
+        assert_eq!(store.delete("a.bin").await.unwrap(), 2);
+        assert!(store.load("a.bin").await.unwrap().is_none());
+        assert_eq!(store.list().await.unwrap(), vec!["b.bin"]);
+        assert_eq!(store.load_catalog().await.unwrap().len(), 1);
+        assert_eq!(store.delete("a.bin").await.unwrap(), 0);
+    }
+}
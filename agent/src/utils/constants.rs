@@ -1,13 +1,89 @@
 pub const LOG_DIRECTORY: &str = "/glacier-logs";
 pub const REPORT_DIRECTORY: &str = "/glacier-reports";
-pub const GLACIER_DIRECTORY: &str = "/glacier";
+
+pub const EXIT_VALID: i32 = 0;
+pub const EXIT_INITIALIZED: i32 = 1;
+pub const EXIT_CORRUPTED: i32 = 2;
+pub const EXIT_MISSING: i32 = 3;
+pub const EXIT_INFRASTRUCTURE: i32 = 4;
+pub const EXIT_CONFIGURATION: i32 = 5;
+
+pub const WATCH_DEBOUNCE_MS: u64 = 2000;
+pub const WATCH_SETTLE_SECONDS: u64 = 60;
+
+pub const AGENT_TEMPORARY_SUFFIXES: &[&str] = &[RESTORE_TEMPORARY_SUFFIX, REPAIR_JOURNAL_SUFFIX];
+
+pub const VAULT_OBJECT_DIRECTORY: &str = "objects";
+pub const VAULT_MANIFEST_MAGIC: &[u8] = b"GLCM";
+pub const VAULT_MANIFEST_VERSION: u8 = 1;
+pub const VAULT_MANIFEST_NAME_DOMAIN: &[u8] = b"glacier-manifest-name-v1";
+pub const VAULT_MANIFEST_KEY_DOMAIN: &[u8] = b"glacier-manifest-key-v1";
+pub const VAULT_CHUNK_DIRECTORY: &str = "chunks";
+pub const VAULT_CHUNK_KEY_FILE: &str = "key.json";
+pub const VAULT_CHUNK_NAME_DOMAIN: &[u8] = b"glacier-chunk-name-v1";
+pub const VAULT_CHUNK_KEY_DOMAIN: &[u8] = b"glacier-chunk-key-v1";
+pub const VAULT_CHUNK_TOMBSTONE_EXTENSION: &str = "gc";
+pub const VAULT_COLLECT_GRACE_HOURS: u64 = 24;
+pub const VAULT_STAGING_DIRECTORY: &str = "staging";
+pub const RESTORE_TEMPORARY_SUFFIX: &str = ".glacier-restore";
+
+pub const PARITY_DATA_SHARDS: usize = 16;
+pub const PARITY_PARITY_SHARDS: usize = 4;
+
+pub const SIGNATURE_STORE_MONGODB: &str = "mongodb";
+pub const SIGNATURE_STORE_SQLITE: &str = "sqlite";
+pub const SIGNATURE_STORE_FILE: &str = "file";
+pub const SIGNATURE_STORE_SQLITE_PATH: &str = "/glacier-signatures/signatures.db";
+pub const SIGNATURE_STORE_FILE_PATH: &str = "/glacier-signatures/signatures.jsonl";
+
+pub const ENCRYPTION_KEY_SIZE: usize = 32;
+pub const ENCRYPTION_KEY_HEX_PREFIX: &str = "hex:";
+pub const ENCRYPTION_KEY_BASE64_PREFIX: &str = "base64:";
+pub const ENCRYPTION_KEY_PASSPHRASE_PREFIX: &str = "passphrase:";
+pub const ENCRYPTION_PASSPHRASE_MIN_LENGTH: usize = 12;
+pub const STREAM_MAGIC: &[u8] = b"GLCS";
+pub const STREAM_VERSION: u8 = 1;
+pub const STREAM_NONCE_PREFIX_SIZE: usize = 7;
+pub const STREAM_TAG_SIZE: usize = 16;
+pub const STREAM_HEADER_SIZE: usize = 4 + 1 + 4 + STREAM_NONCE_PREFIX_SIZE;
+pub const STREAM_SEGMENT_SIZE: usize = 1024 * 64;
+pub const STREAM_MAX_SEGMENT_SIZE: usize = 1024 * 1024 * 16;
+pub const REPAIR_JOURNAL_MAGIC: &[u8] = b"GLRJ";
+pub const REPAIR_JOURNAL_VERSION: u8 = 1;
+pub const REPAIR_JOURNAL_SUFFIX: &str = ".glacier-journal";
+
+pub const ENCRYPTION_KEY_ID_DOMAIN: &[u8] = b"glacier-key-id-v1";
+pub const ENCRYPTION_KDF_PATH: &str = "/glacier-keys/kdf.json";
+
+pub const KDF_ALGORITHM: &str = "argon2id";
+pub const KDF_MEMORY_KIB: u32 = 64 * 1024;
+pub const KDF_ITERATIONS: u32 = 3;
+pub const KDF_PARALLELISM: u32 = 1;
+pub const KDF_SALT_SIZE: usize = 16;
+pub const KDF_KEY_CHECK_DOMAIN: &[u8] = b"glacier-key-check-v1";
+
+pub const SIGNING_KEY_PATH: &str = "/glacier-keys/signing.key";
+pub const RECORD_SIGNATURE_DOMAIN: &[u8] = b"glacier-signature-v1";
+pub const SIGNATURE_AUTHOR_AGENT: &str = "glacier-agent";
+pub const SIGNATURE_REASON_FIRST_SEEN: &str = "first seen";
 
 pub const COLLECTION_NAME_SIGNATURES: &str = "signatures";
 pub const COLLECTION_NAME_CATALOG: &str = "catalog";
-
-pub const CHUNK_SIZE: usize = 1024;
+pub const CATALOG_VERIFIED_STATUSES: &[&str] = &["valid", "initialized", "repaired", "restored", "accepted"];
 
 pub const CDC_WINDOW_SIZE: usize = 48;
 pub const CDC_AVERAGE_CHUNK_SIZE: usize = 1024 * 4;
 pub const CDC_MASK_S: u32 = 13;
-pub const CDC_MASK: u32 = (1 << CDC_MASK_S) - 1;
\ No newline at end of file
+pub const CDC_MASK: u32 = (1 << CDC_MASK_S) - 1;
+pub const CDC_MIN_CHUNK_SIZE: usize = CDC_AVERAGE_CHUNK_SIZE / 4;
+pub const CDC_MAX_CHUNK_SIZE: usize = CDC_AVERAGE_CHUNK_SIZE * 4;
+pub const CDC_READ_BUFFER_SIZE: usize = 1024 * 64;
+
+pub const CDC_GEAR_SEED: u64 = 0x676c_6163_6965_7221;
+pub const CDC_NORMALIZATION_LEVEL: u32 = 2;
+
+pub const CDC_CHUNKER_VERSION_ADDITIVE: u32 = 1;
+pub const CDC_CHUNKER_VERSION_GEAR: u32 = 2;
+pub const CDC_CHUNKER_VERSION: u32 = CDC_CHUNKER_VERSION_GEAR;
+
+pub const CDC_DIFF_MAX_EDITS: usize = 1024;